@@ -1,35 +1,110 @@
 use std::path::Path;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 #[derive(Parser)]
 pub(crate) struct Args {
     #[command(subcommand)]
     pub command: Subcommand,
 
-    /// LSP server command.
-    #[arg(long, default_value_t = Box::from("rust-analyzer"), global = true)]
-    pub lsp: Box<str>,
+    #[command(flatten)]
+    pub server: ServerArgs,
 
     /// Enable verbose debug output.
     #[arg(long, short, default_value_t = false, global = true)]
     pub debug: bool,
+
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+/// LSP server selection, overriding graph settings.
+#[derive(Default, clap::Args)]
+pub(crate) struct ServerArgs {
+    /// LSP server command, used instead of the servers from graph settings.
+    /// Detected from the project files if not configured.
+    #[arg(long, global = true)]
+    pub lsp: Option<Box<str>>,
+
+    /// Argument for the LSP server command. Can be repeated.
+    #[arg(long, allow_hyphen_values = true, global = true)]
+    pub lsp_arg: Vec<String>,
+}
+
+/// Options for updating graph files.
+#[derive(clap::Args)]
+pub(crate) struct WriteArgs {
+    /// Keep the previous contents of updated graph file with `.bak` suffix.
+    #[arg(
+        long,
+        default_value_t = false,
+        global = true,
+        overrides_with = "no_backup"
+    )]
+    pub backup: bool,
+
+    /// Don't keep the previous contents, even if the project configuration
+    /// enables backups.
+    #[arg(
+        long,
+        default_value_t = false,
+        global = true,
+        overrides_with = "backup"
+    )]
+    pub no_backup: bool,
+
+    /// What to do if the graph file was changed since it was loaded.
+    #[arg(long, value_enum, default_value_t = OnConflict::Refuse, global = true)]
+    pub on_conflict: OnConflict,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum OnConflict {
+    /// Leave the file as is and fail.
+    Refuse,
+    /// Apply reference check results on top of the changed file.
+    Merge,
 }
 
 #[derive(Parser)]
 pub(crate) enum Subcommand {
     Verify(VerifyArgs),
     MakeRef(MakeRefArgs),
+    CrateGraph(CrateGraphArgs),
+    CargoGraph(CargoGraphArgs),
+    Merge(MergeArgs),
+    Layout(LayoutArgs),
+    Render(RenderArgs),
+    Export(ExportArgs),
+    Import(ImportArgs),
+    Embed(EmbedArgs),
+    Show(ShowArgs),
+    Browse(BrowseArgs),
+    Init(InitArgs),
 }
 
 #[derive(Parser)]
 pub(crate) struct VerifyArgs {
-    /// Target file to apply sync results to.
-    pub target: Box<Path>,
+    /// Target file to apply sync results to. Graphs listed in the project
+    /// configuration are used if not specified.
+    pub target: Option<Box<Path>>,
 
     /// Apply changes to the target. If not enabled, only a validation will be performed.
-    #[arg(long, short, default_value_t = false)]
+    #[arg(long, short, default_value_t = false, overrides_with = "no_update")]
     pub update: bool,
+
+    /// Only validate the target, even if the project configuration enables updates.
+    #[arg(long, default_value_t = false, overrides_with = "update")]
+    pub no_update: bool,
+
+    /// Workspace root, instead of the one from graph settings.
+    #[arg(long)]
+    pub root: Option<Box<Path>>,
+
+    /// Glob pattern of reference paths to skip, instead of the ones from
+    /// graph settings. Can be repeated.
+    #[arg(long)]
+    pub ignore: Vec<String>,
 }
 
 #[derive(Parser)]
@@ -38,3 +113,174 @@ pub(crate) struct MakeRefArgs {
     /// Run in interactive mode if it's not specified.
     pub target: Option<Box<str>>,
 }
+
+#[derive(Parser)]
+pub(crate) struct CrateGraphArgs {
+    /// Target file to write the imported crate graph to.
+    pub output: Box<Path>,
+
+    /// Include sysroot and dependency crates, not only the workspace members.
+    #[arg(long, default_value_t = false)]
+    pub full: bool,
+}
+
+#[derive(Parser)]
+pub(crate) struct CargoGraphArgs {
+    /// Target file to write the dependency graph to.
+    pub output: Box<Path>,
+
+    /// Include transitive dependencies, not only the direct ones.
+    #[arg(long, default_value_t = false)]
+    pub transitive: bool,
+}
+
+#[derive(Parser)]
+pub(crate) struct MergeArgs {
+    /// Existing graph file to merge entries into.
+    pub target: Box<Path>,
+
+    /// Graph file with the entries to add (e.g. generated one).
+    pub source: Box<Path>,
+
+    /// Write merged graph to a different file instead of updating the target.
+    #[arg(long, short)]
+    pub output: Option<Box<Path>>,
+}
+
+#[derive(Parser)]
+pub(crate) struct LayoutArgs {
+    /// Graph file to position the nodes in.
+    pub target: Box<Path>,
+
+    /// Layout algorithm.
+    #[arg(long, short, value_enum, default_value_t = LayoutAlgorithm::Layered)]
+    pub algorithm: LayoutAlgorithm,
+
+    /// Only position nodes which are placed at the origin (e.g. generated ones).
+    #[arg(long, default_value_t = false)]
+    pub unplaced: bool,
+
+    /// Write the result to a different file instead of updating the target.
+    #[arg(long, short)]
+    pub output: Option<Box<Path>>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum LayoutAlgorithm {
+    /// Force-directed placement.
+    Force,
+    /// Layered placement with edges pointing downwards.
+    Layered,
+}
+
+#[derive(Parser)]
+pub(crate) struct RenderArgs {
+    /// Graph file to render.
+    pub target: Box<Path>,
+
+    /// Output SVG file. Written to stdout if not specified.
+    #[arg(long, short)]
+    pub output: Option<Box<Path>>,
+}
+
+#[derive(Parser)]
+pub(crate) struct ExportArgs {
+    /// Graph file to export.
+    pub target: Box<Path>,
+
+    /// Output format.
+    #[arg(long, short, value_enum)]
+    pub format: ExportFormat,
+
+    /// Output file. Written to stdout if not specified.
+    #[arg(long, short)]
+    pub output: Option<Box<Path>>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// JSON Canvas, as used by Obsidian.
+    Canvas,
+    /// Graphviz DOT.
+    Dot,
+    /// Uncompressed draw.io (diagrams.net) document.
+    Drawio,
+    /// GraphML.
+    Graphml,
+    /// Mermaid flowchart.
+    Mermaid,
+    /// PlantUML component diagram.
+    Plantuml,
+}
+
+#[derive(Parser)]
+pub(crate) struct ImportArgs {
+    /// Diagram file to import.
+    pub source: Box<Path>,
+
+    /// Input format.
+    #[arg(long, short, value_enum)]
+    pub format: ImportFormat,
+
+    /// Output graph file. Written to stdout if not specified.
+    #[arg(long, short)]
+    pub output: Option<Box<Path>>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum ImportFormat {
+    /// JSON Canvas, as used by Obsidian.
+    Canvas,
+    /// Graphviz DOT.
+    Dot,
+    /// GraphML.
+    Graphml,
+    /// Mermaid flowchart.
+    Mermaid,
+}
+
+#[derive(Parser)]
+pub(crate) struct EmbedArgs {
+    /// Markdown files with `<!-- islands:path/to/graph.json -->` ... `<!-- /islands -->` blocks.
+    #[arg(required = true)]
+    pub targets: Vec<Box<Path>>,
+
+    /// Fail if any of the embedded diagrams is outdated instead of updating it.
+    #[arg(long, default_value_t = false)]
+    pub check: bool,
+}
+
+#[derive(Parser)]
+pub(crate) struct ShowArgs {
+    /// Graph file to print.
+    pub target: Box<Path>,
+
+    /// Print indented tree instead of nested boxes.
+    #[arg(long, default_value_t = false)]
+    pub tree: bool,
+
+    /// Use ASCII characters only.
+    #[arg(long, default_value_t = false)]
+    pub ascii: bool,
+
+    /// Maximum line width. Terminal width is used if not specified.
+    #[arg(long, short)]
+    pub width: Option<usize>,
+}
+
+#[derive(Parser)]
+pub(crate) struct BrowseArgs {
+    /// Graph file to browse.
+    pub target: Box<Path>,
+}
+
+#[derive(Parser)]
+pub(crate) struct InitArgs {
+    /// Graph files to list in the configuration. JSON graphs in the current
+    /// directory are used if not specified.
+    pub graphs: Vec<String>,
+
+    /// Replace the existing configuration file.
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+}
@@ -0,0 +1,594 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Context as _;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::DefaultTerminal;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListState, Paragraph, Wrap};
+
+use crate::args::WriteArgs;
+use crate::graph::{Data, Graph};
+use crate::noderef::{NodeRef, RefType};
+use crate::pool::ClientPool;
+use crate::settings::{Resolved, Server};
+use crate::{cargo, show};
+
+/// Action requested by key press which needs access outside of the browser state.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Action {
+    None,
+    Quit,
+    Edit,
+    Resolve,
+    Save,
+}
+
+/// Navigation state of the graph browser.
+pub(crate) struct Browser {
+    pub graph: Graph,
+    /// Index of the focused node.
+    current: usize,
+    /// Previously focused nodes.
+    history: Vec<usize>,
+    /// Selected entry in the list of connected nodes.
+    link: usize,
+    status: String,
+    pub modified: bool,
+}
+
+/// Node connected to the focused one.
+struct Link {
+    node: usize,
+    outgoing: bool,
+    label: Option<String>,
+}
+
+impl Browser {
+    pub fn new(graph: Graph) -> Self {
+        Self {
+            current: first_root(&graph),
+            graph,
+            history: Vec::new(),
+            link: 0,
+            status: String::from("Press ? for help"),
+            modified: false,
+        }
+    }
+
+    /// Nodes which share the parent with the focused node.
+    fn siblings(&self) -> Vec<usize> {
+        let parent = self.data().parent.as_deref();
+        self.nodes_in(parent)
+    }
+
+    fn nodes_in(&self, parent: Option<&str>) -> Vec<usize> {
+        (0..self.graph.nodes.len())
+            .filter(|&index| {
+                let node = &self.graph.nodes[index];
+                !node.removed && node.data.parent.as_deref() == parent
+            })
+            .collect()
+    }
+
+    fn links(&self) -> Vec<Link> {
+        let id = self.data().id.as_str();
+        let mut links = Vec::new();
+        for edge in self.graph.edges.iter().filter(|edge| !edge.removed) {
+            let (source, target) = match (&edge.data.source, &edge.data.target) {
+                (Some(source), Some(target)) => (source.as_str(), target.as_str()),
+                _ => continue,
+            };
+            let (other, outgoing) = match (source == id, target == id) {
+                (true, _) => (target, true),
+                (false, true) => (source, false),
+                _ => continue,
+            };
+            if let Some(node) = self.index_of(other) {
+                links.push(Link {
+                    node,
+                    outgoing,
+                    label: edge.data.label.clone(),
+                });
+            }
+        }
+        links
+    }
+
+    fn index_of(&self, id: &str) -> Option<usize> {
+        self.graph.nodes.iter().position(|node| node.data.id == id)
+    }
+
+    fn data(&self) -> &Data {
+        &self.graph.nodes[self.current].data
+    }
+
+    fn focus(&mut self, index: usize) {
+        if index != self.current {
+            self.history.push(self.current);
+            self.current = index;
+            self.link = 0;
+        }
+    }
+
+    /// Save the graph. Merging changes made to the file reloads the graph, so
+    /// the focus and history are restored by node id.
+    fn save(&mut self, target: &Path, write: &WriteArgs) -> anyhow::Result<()> {
+        let id = |index: usize| self.graph.nodes[index].data.id.clone();
+        let current = id(self.current);
+        let history: Vec<String> = self.history.iter().map(|&index| id(index)).collect();
+        self.graph.save(target, write)?;
+        self.modified = false;
+
+        self.history = history.iter().filter_map(|id| self.index_of(id)).collect();
+        match self.index_of(&current) {
+            Some(index) => self.current = index,
+            None => {
+                self.current = first_root(&self.graph);
+                self.link = 0;
+            }
+        }
+        self.link = self.link.min(self.links().len().saturating_sub(1));
+        Ok(())
+    }
+
+    /// Move focus within siblings without recording the history.
+    fn step(&mut self, offset: isize) {
+        let siblings = self.siblings();
+        let position = siblings.iter().position(|&index| index == self.current);
+        if let Some(position) = position {
+            let next = position
+                .saturating_add_signed(offset)
+                .min(siblings.len() - 1);
+            self.current = siblings[next];
+            self.link = 0;
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> Action {
+        if self.graph.nodes.is_empty() {
+            return match key {
+                KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+                _ => Action::None,
+            };
+        }
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Up | KeyCode::Char('k') => self.step(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.step(1),
+            KeyCode::Left | KeyCode::Char('h') => {
+                let parent = self.data().parent.clone();
+                match parent.and_then(|parent| self.index_of(&parent)) {
+                    Some(parent) => self.focus(parent),
+                    None => self.status = "Node has no parent".into(),
+                }
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                let id = self.data().id.clone();
+                match self.nodes_in(Some(&id)).first() {
+                    Some(&child) => self.focus(child),
+                    None => self.status = "Node has no children".into(),
+                }
+            }
+            KeyCode::Tab | KeyCode::Char('n') => {
+                let count = self.links().len();
+                if count > 0 {
+                    self.link = (self.link + 1) % count;
+                }
+            }
+            KeyCode::BackTab | KeyCode::Char('p') => {
+                let count = self.links().len();
+                if count > 0 {
+                    self.link = (self.link + count - 1) % count;
+                }
+            }
+            KeyCode::Enter => match self.links().get(self.link) {
+                Some(link) => self.focus(link.node),
+                None => self.status = "Node has no edges".into(),
+            },
+            KeyCode::Backspace | KeyCode::Char('b') => {
+                if let Some(previous) = self.history.pop() {
+                    self.current = previous;
+                    self.link = 0;
+                }
+            }
+            KeyCode::Char('e') => return Action::Edit,
+            KeyCode::Char('r') => return Action::Resolve,
+            KeyCode::Char('w') => return Action::Save,
+            KeyCode::Char('?') => {
+                self.status = String::from(
+                    "↑↓ siblings  ← parent  → child  Tab edge  Enter follow  b back  \
+                     e edit  r resolve  w save  q quit",
+                );
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn draw(&self, frame: &mut ratatui::Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [nodes, details] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+
+        let siblings = self.siblings();
+        let items: Vec<Line> = siblings
+            .iter()
+            .map(|&index| {
+                let data = &self.graph.nodes[index].data;
+                let nested = !self.nodes_in(Some(&data.id)).is_empty();
+                let suffix = if nested { " ▸" } else { "" };
+                Line::from(vec![
+                    marker(data),
+                    Span::raw(format!(" {}{suffix}", label(data))),
+                ])
+            })
+            .collect();
+        let title = match self.data().parent.as_deref() {
+            Some(parent) => format!(" {} ", self.breadcrumb(parent)),
+            None => String::from(" Graph "),
+        };
+        let mut state = ListState::default()
+            .with_selected(siblings.iter().position(|&index| index == self.current));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            nodes,
+            &mut state,
+        );
+
+        let links = self.links();
+        let links_height = (links.len() as u16 + 2).clamp(3, 12);
+        let [info, doc, edges] = Layout::vertical([
+            Constraint::Length(8),
+            Constraint::Min(3),
+            Constraint::Length(links_height),
+        ])
+        .areas(details);
+
+        let data = self.data();
+        let field = |name: &'static str, value: Option<&str>| {
+            Line::from(vec![
+                Span::styled(format!("{name:<9}"), Style::new().fg(Color::DarkGray)),
+                Span::raw(value.unwrap_or("-").to_string()),
+            ])
+        };
+        let valid = data.valid.map(|valid| valid.to_string());
+        let lines = vec![
+            field("id", Some(&data.id)),
+            field("label", data.label.as_deref()),
+            field("ref", data.r#ref.as_deref()),
+            field("valid", valid.as_deref()),
+            field("location", data.location.as_deref()),
+            field("note", data.note.as_deref()),
+        ];
+        frame.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(" Node ")),
+            info,
+        );
+        frame.render_widget(
+            Paragraph::new(data.doc.as_deref().unwrap_or(""))
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(" Doc ")),
+            doc,
+        );
+
+        let items: Vec<String> = links
+            .iter()
+            .map(|link| {
+                let arrow = if link.outgoing { "→" } else { "←" };
+                let target = label(&self.graph.nodes[link.node].data);
+                match &link.label {
+                    Some(label) => format!("{arrow} {target} ({label})"),
+                    None => format!("{arrow} {target}"),
+                }
+            })
+            .collect();
+        let mut state =
+            ListState::default().with_selected((!links.is_empty()).then_some(self.link));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(" Edges "))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            edges,
+            &mut state,
+        );
+
+        let modified = if self.modified { "[modified] " } else { "" };
+        frame.render_widget(Paragraph::new(format!("{modified}{}", self.status)), status);
+    }
+
+    /// Path of parent labels.
+    fn breadcrumb(&self, parent: &str) -> String {
+        let mut path = Vec::new();
+        let mut current = self.index_of(parent);
+        while let Some(index) = current {
+            let data = &self.graph.nodes[index].data;
+            path.push(label(data));
+            if path.len() > self.graph.nodes.len() {
+                break;
+            }
+            current = data.parent.as_deref().and_then(|id| self.index_of(id));
+        }
+        path.reverse();
+        path.join(" / ")
+    }
+}
+
+/// Run the browser until the user quits.
+pub(crate) async fn run(
+    browser: &mut Browser,
+    target: &Path,
+    settings: &Resolved,
+    write: &WriteArgs,
+) -> anyhow::Result<()> {
+    // Logs would break the screen, so the status line is used instead.
+    let level = log::max_level();
+    log::set_max_level(log::LevelFilter::Off);
+    let mut terminal = ratatui::init();
+    let result = event_loop(browser, &mut terminal, target, settings, write).await;
+    ratatui::restore();
+    log::set_max_level(level);
+    result
+}
+
+async fn event_loop(
+    browser: &mut Browser,
+    terminal: &mut DefaultTerminal,
+    target: &Path,
+    settings: &Resolved,
+    write: &WriteArgs,
+) -> anyhow::Result<()> {
+    let mut clients = ClientPool::new(&settings.root, false);
+
+    loop {
+        terminal.draw(|frame| browser.draw(frame))?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key.code,
+            _ => continue,
+        };
+
+        match browser.handle_key(key) {
+            Action::None => {}
+            Action::Quit => break,
+            Action::Edit => {
+                let location = browser.data().location.clone();
+                let location = match location {
+                    Some(location) => location,
+                    None => {
+                        browser.status = "Node has no location, resolve it first".into();
+                        continue;
+                    }
+                };
+                ratatui::restore();
+                let result = open_editor(&location);
+                *terminal = ratatui::init();
+                browser.status = match result {
+                    Ok(()) => format!("Edited {location}"),
+                    Err(err) => format!("{err:#}"),
+                };
+            }
+            Action::Resolve => {
+                if let Some(server) = lsp_server(browser.data(), settings)
+                    && !clients.is_running(server)
+                {
+                    browser.status = format!("Starting {server} and waiting for index...");
+                    terminal.draw(|frame| browser.draw(frame))?;
+                }
+                let current = browser.current;
+                let data = &mut browser.graph.nodes[current].data;
+                browser.status = match resolve(data, &mut clients, settings).await {
+                    Ok(true) => format!("Resolved {}", label(data)),
+                    Ok(false) => format!("Reference not found: {}", label(data)),
+                    Err(err) => format!("{err:#}"),
+                };
+                browser.modified = true;
+            }
+            Action::Save => {
+                browser.status = match browser.save(target, write) {
+                    Ok(()) => format!("Saved {}", target.display()),
+                    Err(err) => format!("{err:#}"),
+                };
+            }
+        }
+    }
+
+    clients.exit().await
+}
+
+/// Index of the first top-level node.
+fn first_root(graph: &Graph) -> usize {
+    graph
+        .nodes
+        .iter()
+        .position(|node| !node.removed && node.data.parent.is_none())
+        .unwrap_or(0)
+}
+
+/// Server for the `lsp://` reference of the node.
+fn lsp_server<'a>(data: &Data, settings: &'a Resolved) -> Option<&'a Server> {
+    let mut node_ref = NodeRef::parse_ref(data.r#ref.as_deref()?).ok()?;
+    if !matches!(node_ref.schema, RefType::Lsp) {
+        return None;
+    }
+    node_ref.params.fill_defaults(&settings.default_params);
+    settings.server_for(&node_ref).ok()
+}
+
+/// Check the node reference again and update its state. Returns whether the
+/// reference was found.
+async fn resolve(
+    data: &mut Data,
+    clients: &mut ClientPool,
+    settings: &Resolved,
+) -> anyhow::Result<bool> {
+    let r#ref = data.r#ref.as_deref().context("Node has no reference")?;
+    let mut node_ref = NodeRef::parse_ref(r#ref)?;
+    let found = match node_ref.schema {
+        RefType::Lsp => {
+            node_ref.params.fill_defaults(&settings.default_params);
+            let server = settings.server_for(&node_ref)?;
+            clients
+                .get(server)
+                .await?
+                .find_symbol(&node_ref)
+                .await?
+                .map(|data| (data.hover, Some(data.location)))
+        }
+        RefType::File => {
+            let exists = fs::metadata(settings.root.join(&node_ref.path)).is_ok();
+            data.valid = Some(exists);
+            return Ok(exists);
+        }
+        RefType::Crate => {
+            let workspace = cargo::Workspace::load(&settings.root);
+            workspace
+                .resolve(&node_ref)?
+                .map(|data| (data.doc, data.location))
+        }
+        RefType::Unknown => anyhow::bail!("Unknown reference type: {}", r#ref),
+    };
+
+    data.valid = Some(found.is_some());
+    if let Some((doc, location)) = found {
+        data.doc = Some(doc);
+        if location.is_some() {
+            data.location = location;
+        }
+    }
+    Ok(data.valid == Some(true))
+}
+
+/// Open `$VISUAL` or `$EDITOR` at `path:line` location.
+fn open_editor(location: &str) -> anyhow::Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("Editor command is empty")?;
+
+    let mut command = Command::new(program);
+    command.args(words);
+    match location.rsplit_once(':') {
+        Some((path, line)) if line.parse::<u32>().is_ok() => {
+            command.arg(format!("+{line}")).arg(path);
+        }
+        _ => {
+            command.arg(location);
+        }
+    }
+    let status = command
+        .status()
+        .with_context(|| format!("Unable to start editor '{program}'"))?;
+    if !status.success() {
+        anyhow::bail!("Editor exited with {status}");
+    }
+    Ok(())
+}
+
+fn label(data: &Data) -> &str {
+    data.label.as_deref().unwrap_or(&data.id)
+}
+
+fn marker(data: &Data) -> Span<'static> {
+    let charset = &show::UNICODE;
+    match (data.valid, &data.r#ref) {
+        (Some(true), _) => Span::styled(charset.valid, Style::new().fg(Color::Green)),
+        (Some(false), _) => Span::styled(charset.invalid, Style::new().fg(Color::Red)),
+        (None, Some(_)) => Span::styled(charset.unchecked, Style::new().fg(Color::Yellow)),
+        (None, None) => Span::raw(charset.plain),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn navigate() {
+        use crate::graph::Entry;
+
+        let mut graph = Graph::default();
+        for (id, parent) in [
+            ("core", None),
+            ("parser", Some("core")),
+            ("lexer", Some("core")),
+            ("main", None),
+        ] {
+            let mut data = Data::new(id);
+            data.parent = parent.map(Into::into);
+            graph.nodes.push(Entry::node(data));
+        }
+        let mut edge = Data::link("main->lexer", "main", "lexer");
+        edge.label = Some("uses".into());
+        graph.edges.push(Entry::edge(edge));
+
+        let mut browser = Browser::new(graph);
+        let focused = |browser: &Browser| browser.data().id.clone();
+        assert_eq!(focused(&browser), "core");
+
+        browser.handle_key(KeyCode::Right);
+        assert_eq!(focused(&browser), "parser");
+        browser.handle_key(KeyCode::Down);
+        browser.handle_key(KeyCode::Down);
+        assert_eq!(focused(&browser), "lexer");
+
+        let links = browser.links();
+        assert_eq!(links.len(), 1);
+        assert!(!links[0].outgoing);
+        browser.handle_key(KeyCode::Enter);
+        assert_eq!(focused(&browser), "main");
+
+        browser.handle_key(KeyCode::Backspace);
+        assert_eq!(focused(&browser), "lexer");
+        browser.handle_key(KeyCode::Left);
+        assert_eq!(focused(&browser), "core");
+        browser.handle_key(KeyCode::Up);
+        assert_eq!(focused(&browser), "core");
+
+        assert_eq!(browser.handle_key(KeyCode::Char('r')), Action::Resolve);
+        assert_eq!(browser.handle_key(KeyCode::Char('q')), Action::Quit);
+    }
+
+    #[test]
+    fn save_merged() {
+        use crate::args::OnConflict;
+
+        let dir = TempDir::new("browse");
+        let path = dir.join("graph.json");
+        fs::write(
+            &path,
+            r#"{"nodes": [{"data": {"id": "a"}}, {"data": {"id": "b", "ref": "file://b"}}]}"#,
+        )
+        .unwrap();
+        let write = WriteArgs {
+            backup: false,
+            no_backup: false,
+            on_conflict: OnConflict::Merge,
+        };
+
+        let mut browser = Browser::new(Graph::from_json(&path).unwrap());
+        browser.handle_key(KeyCode::Down);
+        browser.graph.nodes[1].data.valid = Some(true);
+
+        // Node is added before the focused one by the editor.
+        let edited = r#"{"nodes": [
+            {"data": {"id": "a"}}, {"data": {"id": "c"}}, {"data": {"id": "b", "ref": "file://b"}}
+        ]}"#;
+        fs::write(&path, edited).unwrap();
+        browser.save(&path, &write).unwrap();
+        assert_eq!(browser.graph.nodes.len(), 3);
+        assert_eq!(browser.data().id, "b");
+        assert_eq!(browser.data().valid, Some(true));
+        assert!(!browser.modified);
+    }
+}
@@ -0,0 +1,425 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context as _, bail};
+use indexmap::{IndexMap, IndexSet};
+use log::warn;
+use semver::{Version, VersionReq};
+use serde_derive::Deserialize;
+use unwrap_or::unwrap_some_or;
+
+use crate::convert::dot::DotGraph;
+use crate::graph::{Data, Entry, Graph};
+use crate::noderef::NodeRef;
+
+/// Subset of `cargo metadata` output.
+#[derive(Deserialize)]
+pub(crate) struct Metadata {
+    pub packages: Vec<Package>,
+    pub workspace_members: Vec<String>,
+    pub workspace_root: PathBuf,
+    pub resolve: Option<Resolve>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Package {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub manifest_path: PathBuf,
+}
+
+/// Resolved dependency graph.
+#[derive(Deserialize)]
+pub(crate) struct Resolve {
+    pub nodes: Vec<ResolveNode>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ResolveNode {
+    pub id: String,
+    pub dependencies: Vec<String>,
+}
+
+/// Subset of `Cargo.lock` contents.
+#[derive(Deserialize)]
+pub(crate) struct Lockfile {
+    #[serde(default)]
+    pub package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct LockedPackage {
+    pub name: String,
+    pub version: String,
+}
+
+/// Offline view of the cargo workspace which is used to resolve `crate:` references.
+pub(crate) struct Workspace {
+    base: PathBuf,
+    metadata: Option<Metadata>,
+    lock: Option<Lockfile>,
+}
+
+/// Resolved crate information.
+pub(crate) struct CrateData {
+    pub doc: String,
+    pub location: Option<String>,
+}
+
+impl Metadata {
+    /// Run `cargo metadata` in the given directory without touching the network.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let output = Command::new("cargo")
+            .args(["metadata", "--format-version", "1", "--offline"])
+            .current_dir(dir)
+            .output()
+            .context("Unable to run cargo metadata")?;
+        if !output.status.success() {
+            bail!(
+                "cargo metadata failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        serde_json::from_slice(&output.stdout).context("Unable to parse cargo metadata output")
+    }
+
+    /// Find package by its crate name. Crate names use underscores while
+    /// package names may use dashes, so both are considered equal.
+    pub fn find_package(&self, crate_name: &str) -> Option<&Package> {
+        let matches = |pkg: &&Package| same_crate(&pkg.name, crate_name);
+        self.packages
+            .iter()
+            .filter(matches)
+            .find(|pkg| self.is_member(pkg))
+            .or_else(|| self.packages.iter().find(matches))
+    }
+
+    pub fn is_member(&self, package: &Package) -> bool {
+        self.workspace_members.contains(&package.id)
+    }
+
+    fn package_by_id(&self, id: &str) -> Option<&Package> {
+        self.packages.iter().find(|pkg| pkg.id == id)
+    }
+}
+
+impl Lockfile {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        toml::from_str(&fs::read_to_string(path).context("Unable to read Cargo.lock")?)
+            .context("Unable to parse Cargo.lock")
+    }
+}
+
+impl Workspace {
+    /// Collect cargo metadata and lock file. Both are optional: the references
+    /// are checked against whichever is available.
+    pub fn load(dir: &Path) -> Self {
+        let metadata = Metadata::load(dir)
+            .inspect_err(|err| warn!("Cargo metadata is not available: {err:#}"))
+            .ok();
+
+        let root = metadata
+            .as_ref()
+            .map_or(dir, |m| m.workspace_root.as_path());
+        let lock = Lockfile::load(&root.join("Cargo.lock"))
+            .inspect_err(|err| warn!("Cargo.lock is not available: {err:#}"))
+            .ok();
+
+        Self {
+            base: dir.into(),
+            metadata,
+            lock,
+        }
+    }
+
+    /// Find the highest resolved version of the crate which satisfies the reference.
+    pub fn resolve(&self, node_ref: &NodeRef) -> anyhow::Result<Option<CrateData>> {
+        let req = match &node_ref.version {
+            Some(version) => VersionReq::parse(version).context("Invalid version requirement")?,
+            None => VersionReq::STAR,
+        };
+        let name = &node_ref.path;
+
+        let versions: Vec<&str> = match (&self.lock, &self.metadata) {
+            (Some(lock), _) => lock
+                .package
+                .iter()
+                .filter(|pkg| same_crate(&pkg.name, name))
+                .map(|pkg| pkg.version.as_str())
+                .collect(),
+            (None, Some(metadata)) => metadata
+                .packages
+                .iter()
+                .filter(|pkg| same_crate(&pkg.name, name))
+                .map(|pkg| pkg.version.as_str())
+                .collect(),
+            (None, None) => bail!("Neither cargo metadata nor Cargo.lock is available"),
+        };
+
+        let version = versions
+            .into_iter()
+            .filter_map(|v| Version::parse(v).ok())
+            .filter(|v| req.matches(v))
+            .max();
+        let version = match version {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+
+        let package = self.metadata.as_ref().and_then(|metadata| {
+            metadata
+                .packages
+                .iter()
+                .find(|pkg| same_crate(&pkg.name, name) && pkg.version == version.to_string())
+        });
+
+        Ok(Some(match package {
+            Some(package) => CrateData {
+                doc: crate_doc(
+                    &package.name,
+                    &package.version,
+                    package.description.as_deref(),
+                ),
+                location: Some(relative_path(&self.base, &package.manifest_path)),
+            },
+            None => CrateData {
+                doc: crate_doc(name, &version.to_string(), None),
+                location: None,
+            },
+        }))
+    }
+}
+
+/// Package names and crate names are treated as equal with either dashes or underscores.
+fn same_crate(a: &str, b: &str) -> bool {
+    a.replace('-', "_") == b.replace('-', "_")
+}
+
+/// Produce node document for the resolved crate.
+fn crate_doc(name: &str, version: &str, description: Option<&str>) -> String {
+    match description {
+        Some(description) => format!("**{name}** {version}\n\n{}", description.trim()),
+        None => format!("**{name}** {version}"),
+    }
+}
+
+/// Produce semver-compatible requirement for the resolved version: `1.2.3`
+/// becomes `1`, `0.2.3` becomes `0.2`.
+fn compatible_req(version: &str) -> String {
+    match Version::parse(version) {
+        Ok(v) if v.major > 0 => v.major.to_string(),
+        Ok(v) if v.minor > 0 => format!("0.{}", v.minor),
+        _ => version.into(),
+    }
+}
+
+/// Produce the graph of workspace members and their dependencies from cargo
+/// metadata. Only direct dependencies are included unless `transitive` is set.
+pub(crate) fn dependency_graph(metadata: &Metadata, base: &Path, transitive: bool) -> Graph {
+    let resolve: IndexMap<&str, &ResolveNode> = metadata
+        .resolve
+        .iter()
+        .flat_map(|resolve| &resolve.nodes)
+        .map(|node| (node.id.as_str(), node))
+        .collect();
+
+    let mut included: IndexSet<&str> = metadata
+        .workspace_members
+        .iter()
+        .map(String::as_str)
+        .collect();
+    let mut index = 0;
+    while let Some(&id) = included.get_index(index) {
+        let is_member = index < metadata.workspace_members.len();
+        index += 1;
+        if !is_member && !transitive {
+            continue;
+        }
+        if let Some(node) = resolve.get(id) {
+            included.extend(node.dependencies.iter().map(String::as_str));
+        }
+    }
+
+    let mut graph = Graph::default();
+    let mut ids = IndexMap::<&str, String>::new();
+
+    for &package_id in &included {
+        let package = match metadata.package_by_id(package_id) {
+            Some(package) => package,
+            None => continue,
+        };
+        let duplicate = metadata
+            .packages
+            .iter()
+            .any(|pkg| pkg.name == package.name && pkg.id != package.id);
+        let id = if duplicate {
+            format!("crate-{}-{}", package.name, package.version)
+        } else {
+            format!("crate-{}", package.name)
+        };
+
+        let mut data = Data::new(&id);
+        data.label = Some(package.name.clone());
+        data.r#ref = Some(format!(
+            "crate:{}@{}",
+            package.name,
+            compatible_req(&package.version)
+        ));
+        data.valid = Some(true);
+        data.doc = Some(crate_doc(
+            &package.name,
+            &package.version,
+            package.description.as_deref(),
+        ));
+        data.location = Some(relative_path(base, &package.manifest_path));
+        graph.nodes.push(Entry::node(data));
+        ids.insert(package_id, id);
+    }
+
+    for (&package_id, source) in &ids {
+        let node = unwrap_some_or!(resolve.get(package_id), { continue });
+        for dependency in &node.dependencies {
+            let target = unwrap_some_or!(ids.get(dependency.as_str()), { continue });
+            let data = Data::link(format!("{source}->{target}"), source, target);
+            graph.edges.push(Entry::edge(data));
+        }
+    }
+
+    graph
+}
+
+/// Convert manifest path into `file://` reference, relative to `base` when possible.
+pub(crate) fn manifest_ref(base: &Path, manifest_path: &Path) -> String {
+    format!("file://{}", relative_path(base, manifest_path))
+}
+
+fn relative_path(base: &Path, path: &Path) -> String {
+    path.strip_prefix(base)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Convert rust-analyzer crate graph into graph entries. Nodes are linked to
+/// their manifests when the package could be found in cargo metadata.
+pub(crate) fn crate_graph(dot: &DotGraph, metadata: Option<&Metadata>, base: &Path) -> Graph {
+    let mut graph = Graph::default();
+    let mut ids = IndexMap::<&str, String>::new();
+
+    for (dot_id, attrs) in &dot.nodes {
+        let name = attrs.get("label").map_or(dot_id.as_str(), String::as_str);
+        let mut id = format!("crate-{name}");
+        if ids.values().any(|existing| *existing == id) {
+            id = format!("crate-{name}-{dot_id}");
+        }
+
+        let mut data = Data::new(&id);
+        data.label = Some(name.into());
+        if let Some(package) = metadata.and_then(|m| m.find_package(name)) {
+            data.r#ref = Some(manifest_ref(base, &package.manifest_path));
+        }
+        graph.nodes.push(Entry::node(data));
+        ids.insert(dot_id, id);
+    }
+
+    for edge in &dot.edges {
+        let (Some(source), Some(target)) =
+            (ids.get(edge.source.as_str()), ids.get(edge.target.as_str()))
+        else {
+            continue;
+        };
+        let mut data = Data::link(format!("{source}->{target}"), source, target);
+        data.label = edge.attrs.get("label").filter(|l| !l.is_empty()).cloned();
+        graph.edges.push(Entry::edge(data));
+    }
+
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_graph_entries() {
+        let dot = DotGraph::parse(
+            r#"digraph rust_analyzer_crate_graph {
+    _0[label="core"][shape="box"];
+    _1[label="islands_sync_lsp"][shape="box"];
+    _1 -> _0[label=""];
+}"#,
+        )
+        .unwrap();
+        let metadata = Metadata {
+            packages: vec![Package {
+                id: "path+file:///work/islands-sync-lsp#0.1.1".into(),
+                name: "islands-sync-lsp".into(),
+                version: "0.1.1".into(),
+                description: None,
+                manifest_path: "/work/Cargo.toml".into(),
+            }],
+            workspace_members: vec!["path+file:///work/islands-sync-lsp#0.1.1".into()],
+            workspace_root: "/work".into(),
+            resolve: None,
+        };
+
+        let graph = crate_graph(&dot, Some(&metadata), Path::new("/work"));
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.nodes[0].data.r#ref, None);
+        assert_eq!(graph.nodes[1].data.id, "crate-islands_sync_lsp");
+        assert_eq!(
+            graph.nodes[1].data.r#ref.as_deref(),
+            Some("file://Cargo.toml")
+        );
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(
+            graph.edges[0].data.source.as_deref(),
+            Some("crate-islands_sync_lsp")
+        );
+        assert_eq!(graph.edges[0].data.target.as_deref(), Some("crate-core"));
+    }
+
+    #[test]
+    fn compatible_version_req() {
+        assert_eq!(compatible_req("1.0.228"), "1");
+        assert_eq!(compatible_req("0.7.3"), "0.7");
+        assert_eq!(compatible_req("0.0.3"), "0.0.3");
+    }
+
+    #[test]
+    fn resolve_from_lockfile() {
+        let lock: Lockfile = toml::from_str(
+            r#"
+version = 4
+
+[[package]]
+name = "serde"
+version = "1.0.228"
+
+[[package]]
+name = "toml"
+version = "0.5.11"
+
+[[package]]
+name = "toml"
+version = "0.9.8"
+"#,
+        )
+        .unwrap();
+        let workspace = Workspace {
+            base: "/work".into(),
+            metadata: None,
+            lock: Some(lock),
+        };
+
+        let resolve = |r: &str| workspace.resolve(&NodeRef::parse_ref(r).unwrap()).unwrap();
+        assert_eq!(resolve("crate:serde@1").unwrap().doc, "**serde** 1.0.228");
+        assert_eq!(resolve("crate:toml").unwrap().doc, "**toml** 0.9.8");
+        assert_eq!(resolve("crate:toml@0.5").unwrap().doc, "**toml** 0.5.11");
+        assert!(resolve("crate:serde@2").is_none());
+        assert!(resolve("crate:tokio").is_none());
+    }
+}
@@ -1,25 +1,37 @@
 use crate::noderef::NodeRef;
+use crate::settings::{Readiness, Server};
+use std::collections::HashSet;
 use std::ops::ControlFlow;
+use std::path::Path;
 use std::process::Stdio;
+use std::time::Duration;
 
-use anyhow::Context as _;
+use anyhow::{Context as _, anyhow, bail};
 use async_lsp::concurrency::ConcurrencyLayer;
+use async_lsp::lsp_types::notification::Notification;
+use async_lsp::lsp_types::request::{
+    DocumentSymbolRequest, HoverRequest, Request, WorkspaceSymbolRequest,
+};
 use async_lsp::lsp_types::{
     ClientCapabilities, DocumentSymbol, DocumentSymbolClientCapabilities, DocumentSymbolParams,
     DocumentSymbolResponse, Hover, HoverClientCapabilities, HoverContents, HoverParams,
     InitializeParams, InitializedParams, MarkupKind, NumberOrString, ProgressParams,
     ProgressParamsValue, SymbolInformation, TextDocumentClientCapabilities, TextDocumentIdentifier,
-    TextDocumentPositionParams, Url, WindowClientCapabilities, WorkDoneProgress, WorkspaceFolder,
-    WorkspaceSymbolParams, WorkspaceSymbolResponse,
+    TextDocumentPositionParams, Url, WindowClientCapabilities, WorkDoneProgress,
+    WorkDoneProgressCreateParams, WorkspaceFolder, WorkspaceSymbolParams, WorkspaceSymbolResponse,
 };
 use async_lsp::panic::CatchUnwindLayer;
 use async_lsp::router::Router;
 use async_lsp::tracing::TracingLayer;
-use async_lsp::{LanguageClient, LanguageServer, ResponseError, ServerSocket};
+use async_lsp::{ErrorCode, LanguageClient, LanguageServer, ResponseError, ServerSocket};
 use async_process::Child;
-use futures::channel::oneshot;
+use futures::StreamExt as _;
+use futures::channel::mpsc;
+use futures::future::BoxFuture;
 use log::{debug, error, info};
+use serde_derive::{Deserialize, Serialize};
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use tower::ServiceBuilder;
 use unwrap_or::unwrap_some_or;
 
@@ -28,28 +40,84 @@ pub(crate) struct LspClient {
     #[expect(unused)]
     child: Child,
     workdir: Url,
+    initialization_options: Option<serde_json::Value>,
     server: ServerSocket,
-    indexed_recv: Option<oneshot::Receiver<()>>,
+    ready: Ready,
+    ready_timeout: Option<Duration>,
+    ready_recv: Option<mpsc::UnboundedReceiver<ReadyEvent>>,
+    retry: Retry,
     join: Option<JoinHandle<()>>,
 }
 
-/// List of known indexing tokens.
+/// Indexing tokens used when the server profile has none.
 const INDEXING_TOKENS: &[&str] = &["rustAnalyzer/Indexing", "rustAnalyzer/cachePriming"];
 
+/// Quiet period used when the server profile has none.
+const QUIET_PERIOD: Duration = Duration::from_millis(1000);
+
+/// Index wait timeout used when the server profile has none.
+const READY_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Request timeout used when the server profile has none.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Request retries used when the server profile has none.
+const REQUEST_RETRIES: u32 = 3;
+
+/// Delay before the first request retry.
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Readiness strategy with its parameters.
+enum Ready {
+    Tokens(Vec<String>),
+    Progress(Duration),
+    ServerStatus,
+}
+
+/// Server notification relevant for the index readiness.
+#[derive(Debug)]
+enum ReadyEvent {
+    Begin(NumberOrString),
+    End(NumberOrString),
+    Status { quiescent: bool },
+}
+
 impl LspClient {
-    /// Spawn LSP server child process.
-    pub fn new(cmd: &str, debug: bool) -> anyhow::Result<Self> {
-        let cwd = std::env::current_dir()?;
-        let workdir: Url = format!("file://{}/", cwd.display()).parse()?;
-
-        let (indexed_send, indexed_recv) = oneshot::channel();
-        let mut router = Router::from_language_client(LspState {
-            indexed_send: Some(indexed_send),
-        });
+    /// Spawn LSP server child process for the workspace `root` directory.
+    pub fn new(server: &Server, root: &Path, debug: bool) -> anyhow::Result<Self> {
+        let workdir: Url = format!("file://{}/", root.display()).parse()?;
+
+        let (ready_send, ready_recv) = mpsc::unbounded();
+        let initialization_options = server.initialization_options.clone();
+        let ready = match server.readiness() {
+            Readiness::Tokens if server.indexing_tokens.is_empty() => Ready::Tokens(
+                INDEXING_TOKENS
+                    .iter()
+                    .map(|token| token.to_string())
+                    .collect(),
+            ),
+            Readiness::Tokens => Ready::Tokens(server.indexing_tokens.clone()),
+            Readiness::Progress => Ready::Progress(
+                server
+                    .quiet_period
+                    .map_or(QUIET_PERIOD, Duration::from_millis),
+            ),
+            Readiness::ServerStatus => Ready::ServerStatus,
+        };
+        let ready_timeout = timeout(server.ready_timeout, READY_TIMEOUT);
+        let retry = Retry {
+            timeout: timeout(server.request_timeout, REQUEST_TIMEOUT),
+            retries: server.request_retries.unwrap_or(REQUEST_RETRIES),
+            delay: RETRY_DELAY,
+        };
+        let mut router = Router::from_language_client(LspState { ready_send });
         router.event(LspState::stop);
+        router.notification::<ServerStatus>(LspState::server_status);
 
-        let mut child = async_process::Command::new(cmd)
-            .current_dir(&cwd)
+        let mut child = async_process::Command::new(&server.command)
+            .args(&server.args)
+            .envs(&server.env)
+            .current_dir(root)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(if debug {
@@ -59,7 +127,7 @@ impl LspClient {
             })
             .kill_on_drop(true)
             .spawn()
-            .context("Failed run rust-analyzer")?;
+            .with_context(|| format!("Failed to run {}", server.command))?;
 
         let (mainloop, server) = async_lsp::MainLoop::new_client(|_server| {
             ServiceBuilder::new()
@@ -90,9 +158,13 @@ impl LspClient {
         Ok(Self {
             child,
             workdir,
+            initialization_options,
             server,
+            ready,
+            ready_timeout,
+            ready_recv: Some(ready_recv),
+            retry,
             join: Some(mainloop_handle),
-            indexed_recv: Some(indexed_recv),
         })
     }
 
@@ -105,6 +177,7 @@ impl LspClient {
                     name: "root".into(),
                     uri: self.workdir.clone(),
                 }]),
+                initialization_options: self.initialization_options.clone(),
                 capabilities: ClientCapabilities {
                     window: Some(WindowClientCapabilities {
                         work_done_progress: Some(true),
@@ -121,6 +194,7 @@ impl LspClient {
                         }),
                         ..Default::default()
                     }),
+                    experimental: Some(serde_json::json!({ "serverStatusNotification": true })),
                     ..Default::default()
                 },
                 ..Default::default()
@@ -147,31 +221,33 @@ impl LspClient {
     pub async fn wait_index(&mut self) -> anyhow::Result<()> {
         info!("Waiting for index to be loaded...");
 
-        let recv = self
-            .indexed_recv
+        let mut recv = self
+            .ready_recv
             .take()
-            .context("Unable to get indexed recv")?;
-        recv.await.context("Unable to wait for indexing completion")
+            .context("Unable to get readiness recv")?;
+        let wait = wait_ready(&self.ready, &mut recv);
+        match self.ready_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, wait)
+                .await
+                .map_err(|_| anyhow!("Index is not ready after {}s", timeout.as_secs_f32()))?,
+            None => wait.await,
+        }
     }
 
     /// Perform a workspace lookup for specific symbol.
-    /// Server response items are treated as 'missing symbols' to handle changed
-    /// file paths.
+    /// Symbols in files which don't exist are missing to handle changed file
+    /// paths. Server errors are returned as is, not treated as missing.
     pub async fn find_symbol(&mut self, node_ref: &NodeRef) -> anyhow::Result<Option<LspData>> {
-        if node_ref.path.len() > 0 {
-            match self.find_document_symbol(&node_ref).await {
-                Ok(symbol) => return Ok(symbol),
-                Err(err) => {
-                    for cause in err.chain() {
-                        if let Some(_err) = cause.downcast_ref::<ResponseError>() {
-                            return Ok(None);
-                        }
-                    }
-                    return Err(err);
-                }
+        if !node_ref.path.is_empty() {
+            let uri = self.workdir.join(&node_ref.path)?;
+            let exists = uri.to_file_path().is_ok_and(|path| path.exists());
+            if !exists {
+                debug!("Referenced file does not exist: {}", node_ref.path);
+                return Ok(None);
             }
+            return self.find_document_symbol(node_ref).await;
         }
-        self.find_workspace_symbol(&node_ref).await
+        self.find_workspace_symbol(node_ref).await
     }
 
     /// Use provided file path, line and char number to produce LSP reference (`lsp://...`).
@@ -185,8 +261,7 @@ impl LspClient {
 
         let uri = self.workdir.join(path)?;
         let symbol = self
-            .server
-            .document_symbol(DocumentSymbolParams {
+            .request::<DocumentSymbolRequest>(DocumentSymbolParams {
                 text_document: TextDocumentIdentifier { uri },
                 work_done_progress_params: Default::default(),
                 partial_result_params: Default::default(),
@@ -200,13 +275,20 @@ impl LspClient {
                 if stack.is_empty() {
                     return Ok(None);
                 }
-                return Ok(Some(convert_stack(path, stack)));
+                Ok(Some(convert_stack(path, stack)))
             }
             Some(DocumentSymbolResponse::Flat(_)) => todo!(),
             None => todo!(),
         }
     }
 
+    /// Request the workspace crate graph in DOT format (rust-analyzer extension).
+    pub async fn view_crate_graph(&mut self, full: bool) -> anyhow::Result<String> {
+        self.request::<ViewCrateGraph>(ViewCrateGraphParams { full })
+            .await
+            .context("Unable to request crate graph")
+    }
+
     /// Wait for LSP server child process completion.
     pub async fn exit(&mut self) -> anyhow::Result<()> {
         self.server.shutdown(()).await?;
@@ -223,14 +305,25 @@ impl LspClient {
 }
 
 impl LspClient {
+    /// Send the request, waiting for the response up to the request timeout.
+    /// Requests cancelled by the server or with modified content are repeated
+    /// with increasing delay.
+    async fn request<R: Request>(&self, params: R::Params) -> anyhow::Result<R::Result>
+    where
+        R::Params: Clone,
+    {
+        self.retry
+            .run(R::METHOD, || self.server.request::<R>(params.clone()))
+            .await
+    }
+
     /// Query the specified workspace path.
     async fn find_workspace_symbol(
         &mut self,
         node_ref: &NodeRef,
     ) -> anyhow::Result<Option<LspData>> {
         let symbol = self
-            .server
-            .symbol(WorkspaceSymbolParams {
+            .request::<WorkspaceSymbolRequest>(WorkspaceSymbolParams {
                 query: node_ref.hash.clone(),
                 ..Default::default()
             })
@@ -239,7 +332,7 @@ impl LspClient {
 
         match symbol {
             Some(WorkspaceSymbolResponse::Flat(symbols)) => {
-                self.match_flat_symbol(symbols, &node_ref).await
+                self.match_flat_symbol(symbols, node_ref).await
             }
             _ => Ok(None),
         }
@@ -253,8 +346,7 @@ impl LspClient {
         debug!("Query document symbols: {}", node_ref.path);
         let uri = self.workdir.join(&node_ref.path)?;
         let symbol = self
-            .server
-            .document_symbol(DocumentSymbolParams {
+            .request::<DocumentSymbolRequest>(DocumentSymbolParams {
                 text_document: TextDocumentIdentifier { uri },
                 work_done_progress_params: Default::default(),
                 partial_result_params: Default::default(),
@@ -267,14 +359,14 @@ impl LspClient {
                     "Document contains {} symbols, flat structure",
                     symbols.len()
                 );
-                self.match_flat_symbol(symbols, &node_ref).await
+                self.match_flat_symbol(symbols, node_ref).await
             }
             Some(DocumentSymbolResponse::Nested(symbols)) => {
                 debug!(
                     "Document contains {} top level symbols, nested",
                     symbols.len()
                 );
-                self.match_nested_symbol(symbols, &node_ref).await
+                self.match_nested_symbol(symbols, node_ref).await
             }
             _ => Ok(None),
         }
@@ -295,8 +387,7 @@ impl LspClient {
             }
 
             let hover = self
-                .server
-                .hover(HoverParams {
+                .request::<HoverRequest>(HoverParams {
                     text_document_position_params: TextDocumentPositionParams {
                         text_document: TextDocumentIdentifier {
                             uri: s.location.uri.clone(),
@@ -337,8 +428,7 @@ impl LspClient {
         let uri = self.workdir.join(&node_ref.path)?;
 
         let hover = self
-            .server
-            .hover(HoverParams {
+            .request::<HoverRequest>(HoverParams {
                 text_document_position_params: TextDocumentPositionParams {
                     text_document: TextDocumentIdentifier { uri },
                     position: symbol.selection_range.start,
@@ -442,6 +532,109 @@ impl LspClient {
     }
 }
 
+/// Timeout from the server profile, zero disables it.
+fn timeout(secs: Option<u64>, default: Duration) -> Option<Duration> {
+    match secs {
+        Some(0) => None,
+        Some(secs) => Some(Duration::from_secs(secs)),
+        None => Some(default),
+    }
+}
+
+/// Timeout and retries of a request.
+struct Retry {
+    timeout: Option<Duration>,
+    retries: u32,
+    /// Delay before the first retry, doubled for the next ones.
+    delay: Duration,
+}
+
+impl Retry {
+    /// Wait for the request `send` makes, and make it again if it failed for
+    /// a transient reason.
+    async fn run<T, F>(&self, method: &str, mut send: impl FnMut() -> F) -> anyhow::Result<T>
+    where
+        F: Future<Output = async_lsp::Result<T>>,
+    {
+        let mut delay = self.delay;
+        let mut retries = self.retries;
+        loop {
+            let request = send();
+            let result = match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, request)
+                    .await
+                    .map_err(|_| anyhow!("{method} timed out after {}s", timeout.as_secs_f32()))?,
+                None => request.await,
+            };
+            match result {
+                Err(async_lsp::Error::Response(err)) if is_retryable(&err) && retries > 0 => {
+                    debug!("{method} failed, retrying in {delay:?}: {}", err.message);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    retries -= 1;
+                }
+                result => return result.with_context(|| format!("{method} failed")),
+            }
+        }
+    }
+}
+
+/// Whether the request failed for a transient reason and may be repeated.
+fn is_retryable(err: &ResponseError) -> bool {
+    matches!(
+        err.code,
+        ErrorCode::CONTENT_MODIFIED | ErrorCode::SERVER_CANCELLED | ErrorCode::REQUEST_CANCELLED
+    )
+}
+
+/// Wait for the readiness events required by the strategy.
+async fn wait_ready(
+    ready: &Ready,
+    recv: &mut mpsc::UnboundedReceiver<ReadyEvent>,
+) -> anyhow::Result<()> {
+    let mut running = HashSet::new();
+    // Quiet period starts with the wait, so servers which report no progress
+    // are ready after it. Progress events start it again.
+    let mut deadline = match ready {
+        Ready::Progress(quiet) => Instant::now() + *quiet,
+        _ => Instant::now(),
+    };
+    loop {
+        let event = match ready {
+            Ready::Progress(_) if running.is_empty() => {
+                match tokio::time::timeout_at(deadline, recv.next()).await {
+                    Ok(event) => event,
+                    Err(_) => return Ok(()),
+                }
+            }
+            _ => recv.next().await,
+        };
+        let event = match event {
+            Some(event) => event,
+            None => bail!("Server stopped before the index was ready"),
+        };
+        debug!("Readiness event: {event:?}");
+
+        match (ready, event) {
+            (Ready::Tokens(tokens), ReadyEvent::End(NumberOrString::String(token)))
+                if tokens.contains(&token) =>
+            {
+                return Ok(());
+            }
+            (Ready::Progress(quiet), ReadyEvent::Begin(token)) => {
+                running.insert(token);
+                deadline = Instant::now() + *quiet;
+            }
+            (Ready::Progress(quiet), ReadyEvent::End(token)) => {
+                running.remove(&token);
+                deadline = Instant::now() + *quiet;
+            }
+            (Ready::ServerStatus, ReadyEvent::Status { quiescent: true }) => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
 /// Remove extra symbols from name and replace spaces and special chars with '-'.
 fn convert_name(name: &str) -> String {
     let mut out = String::new();
@@ -474,40 +667,77 @@ fn convert_stack(path: &str, stack: Vec<&DocumentSymbol>) -> String {
     base
 }
 
-#[test]
-fn name_conversion() {
-    assert_eq!(
-        convert_name("impl MyTrait   for   MyType<R>"),
-        "impl+MyTrait+for+MyType+R+"
-    );
+struct LspStop;
+
+/// `rust-analyzer/viewCrateGraph` request.
+enum ViewCrateGraph {}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ViewCrateGraphParams {
+    /// Include non-workspace crates (sysroot and dependencies).
+    full: bool,
 }
 
-struct LspStop;
+impl Request for ViewCrateGraph {
+    type Params = ViewCrateGraphParams;
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/viewCrateGraph";
+}
+
+/// `experimental/serverStatus` notification (rust-analyzer extension).
+enum ServerStatus {}
+
+#[derive(Serialize, Deserialize)]
+struct ServerStatusParams {
+    /// Whether all pending work, including indexing, is done.
+    quiescent: bool,
+}
+
+impl Notification for ServerStatus {
+    type Params = ServerStatusParams;
+    const METHOD: &'static str = "experimental/serverStatus";
+}
 
 struct LspState {
-    indexed_send: Option<oneshot::Sender<()>>,
+    ready_send: mpsc::UnboundedSender<ReadyEvent>,
 }
 
 impl LspState {
     fn stop(&mut self, _: LspStop) -> ControlFlow<async_lsp::Result<()>> {
         ControlFlow::Break(Ok(()))
     }
+
+    fn server_status(&mut self, params: ServerStatusParams) -> ControlFlow<async_lsp::Result<()>> {
+        // Nobody listens after the index is ready.
+        let _ = self.ready_send.unbounded_send(ReadyEvent::Status {
+            quiescent: params.quiescent,
+        });
+        ControlFlow::Continue(())
+    }
 }
 
 impl LanguageClient for LspState {
     type Error = ResponseError;
     type NotifyResult = ControlFlow<async_lsp::Result<()>>;
 
+    fn work_done_progress_create(
+        &mut self,
+        _: WorkDoneProgressCreateParams,
+    ) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async { Ok(()) })
+    }
+
     fn progress(&mut self, params: ProgressParams) -> Self::NotifyResult {
-        if matches!(
-            params.value,
-            ProgressParamsValue::WorkDone(WorkDoneProgress::End(_))
-        ) && matches!(params.token, NumberOrString::String(ref s) if INDEXING_TOKENS.contains(&&**s))
-        {
-            if let Some(tx) = self.indexed_send.take() {
-                let _ = tx.send(());
+        let event = match params.value {
+            ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(_)) => {
+                ReadyEvent::Begin(params.token)
             }
-        }
+            ProgressParamsValue::WorkDone(WorkDoneProgress::End(_)) => {
+                ReadyEvent::End(params.token)
+            }
+            _ => return ControlFlow::Continue(()),
+        };
+        let _ = self.ready_send.unbounded_send(event);
         ControlFlow::Continue(())
     }
 }
@@ -529,3 +759,129 @@ impl LspData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn readiness_strategies() {
+        let (send, mut recv) = mpsc::unbounded();
+        let token = |token: &str| NumberOrString::String(token.into());
+
+        // Unrelated tokens are skipped.
+        let ready = Ready::Tokens(vec!["indexing".into()]);
+        send.unbounded_send(ReadyEvent::End(token("loading")))
+            .unwrap();
+        send.unbounded_send(ReadyEvent::End(token("indexing")))
+            .unwrap();
+        wait_ready(&ready, &mut recv).await.unwrap();
+
+        // Server which reports no progress is ready after the quiet period.
+        let ready = Ready::Progress(Duration::from_millis(20));
+        let wait = tokio::time::timeout(Duration::from_millis(100), wait_ready(&ready, &mut recv));
+        assert!(wait.await.is_ok_and(|result| result.is_ok()));
+
+        // Quiet period counts once all progress ends.
+        send.unbounded_send(ReadyEvent::Begin(token("a"))).unwrap();
+        send.unbounded_send(ReadyEvent::Begin(NumberOrString::Number(1)))
+            .unwrap();
+        send.unbounded_send(ReadyEvent::End(token("a"))).unwrap();
+        let wait = tokio::time::timeout(Duration::from_millis(100), wait_ready(&ready, &mut recv));
+        assert!(wait.await.is_err());
+        send.unbounded_send(ReadyEvent::End(NumberOrString::Number(1)))
+            .unwrap();
+        wait_ready(&ready, &mut recv).await.unwrap();
+
+        let ready = Ready::ServerStatus;
+        send.unbounded_send(ReadyEvent::Status { quiescent: false })
+            .unwrap();
+        send.unbounded_send(ReadyEvent::Status { quiescent: true })
+            .unwrap();
+        wait_ready(&ready, &mut recv).await.unwrap();
+
+        drop(send);
+        assert!(wait_ready(&ready, &mut recv).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn request_retries() {
+        use std::cell::Cell;
+        use std::time::Instant;
+
+        let retry = |retries| Retry {
+            timeout: Some(Duration::from_millis(50)),
+            retries,
+            delay: Duration::from_millis(10),
+        };
+        let error = |code| {
+            Err::<(), _>(async_lsp::Error::Response(ResponseError::new(
+                code, "failed",
+            )))
+        };
+
+        // Transient errors are retried with increasing delay.
+        let calls = Cell::new(0);
+        let start = Instant::now();
+        let result = retry(3)
+            .run("test", || {
+                calls.set(calls.get() + 1);
+                let code = match calls.get() {
+                    1 => ErrorCode::CONTENT_MODIFIED,
+                    2 => ErrorCode::SERVER_CANCELLED,
+                    _ => return futures::future::ready(Ok(())),
+                };
+                futures::future::ready(error(code))
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(calls.get(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(30));
+
+        // Retries stop after the limit, with the error kept.
+        calls.set(0);
+        let err = retry(2)
+            .run("test", || {
+                calls.set(calls.get() + 1);
+                futures::future::ready(error(ErrorCode::CONTENT_MODIFIED))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(calls.get(), 3);
+        assert!(matches!(
+            err.downcast_ref::<async_lsp::Error>(),
+            Some(async_lsp::Error::Response(err)) if err.code == ErrorCode::CONTENT_MODIFIED
+        ));
+
+        // Other errors are not retried.
+        calls.set(0);
+        let result = retry(2)
+            .run("test", || {
+                calls.set(calls.get() + 1);
+                futures::future::ready(error(ErrorCode::INVALID_PARAMS))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+
+        // Stuck request times out.
+        calls.set(0);
+        let err = retry(2)
+            .run("test", || {
+                calls.set(calls.get() + 1);
+                futures::future::pending::<async_lsp::Result<()>>()
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "test timed out after 0.05s");
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn name_conversion() {
+        assert_eq!(
+            convert_name("impl MyTrait   for   MyType<R>"),
+            "impl+MyTrait+for+MyType+R+"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, bail};
+use indexmap::IndexMap;
+use serde_derive::Deserialize;
+
+use crate::settings::Server;
+
+/// Name of the project configuration file.
+pub(crate) const FILE_NAME: &str = "islands.toml";
+
+/// Project configuration, shared by all graphs of the project.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Directory of the configuration file. Paths are relative to it.
+    #[serde(skip)]
+    pub dir: PathBuf,
+
+    /// Profile used for references without a matching route.
+    #[serde(default)]
+    pub server: Option<String>,
+
+    /// Named LSP server profiles.
+    #[serde(default)]
+    pub servers: IndexMap<String, Server>,
+
+    /// Profiles for references with matching paths, the first match is used.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+
+    /// Graph files of the project.
+    #[serde(default)]
+    pub graphs: Vec<PathBuf>,
+
+    #[serde(default)]
+    pub verify: VerifyDefaults,
+}
+
+/// Server profile for the reference paths matching the glob pattern.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Route {
+    pub path: String,
+    pub server: String,
+}
+
+/// Defaults for `verify` options.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct VerifyDefaults {
+    #[serde(default)]
+    pub update: bool,
+    #[serde(default)]
+    pub backup: bool,
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+impl Config {
+    /// Look for the configuration file in the directory and its parents.
+    pub fn find(dir: &Path) -> anyhow::Result<Option<Self>> {
+        for dir in dir.ancestors() {
+            let path = dir.join(FILE_NAME);
+            if path.is_file() {
+                return Self::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        let mut config =
+            Self::parse(&text).with_context(|| format!("Unable to parse {}", path.display()))?;
+        config.dir = path.parent().unwrap_or(Path::new(".")).into();
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        let names = config
+            .server
+            .iter()
+            .chain(config.routes.iter().map(|route| &route.server));
+        for name in names {
+            if !config.servers.contains_key(name) {
+                bail!("Unknown server profile: {name}");
+            }
+        }
+        Ok(config)
+    }
+
+    /// Profile for references without a route: the selected one, or the only
+    /// one defined.
+    pub fn default_server(&self) -> Option<&Server> {
+        match &self.server {
+            Some(name) => self.servers.get(name),
+            None if self.servers.len() == 1 => self.servers.values().next(),
+            None => None,
+        }
+    }
+
+    /// Graph file paths resolved against the configuration directory.
+    pub fn graphs(&self) -> Vec<PathBuf> {
+        self.graphs.iter().map(|path| self.dir.join(path)).collect()
+    }
+}
+
+/// Produce the configuration file contents with the given graph files and
+/// commented examples of other options.
+pub(crate) fn scaffold(graphs: &[String]) -> String {
+    let graphs = graphs
+        .iter()
+        .map(|graph| toml::Value::from(graph.as_str()).to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        r#"# Islands project configuration. Paths are relative to this file.
+
+# Graph files checked by `verify` when no target is given.
+graphs = [{graphs}]
+
+# Server profile for references without a matching route.
+server = "rust"
+
+[servers.rust]
+command = "rust-analyzer"
+args = []
+# Referenced files handled by the profile, unless routed otherwise.
+extensions = ["rs"]
+# When the workspace is indexed: "tokens" waits for the end of progress with
+# one of `indexingTokens`, "progress" waits until no progress runs or changes
+# for `quietPeriod` milliseconds, and "serverStatus" waits for rust-analyzer
+# quiescent status.
+readiness = "tokens"
+indexingTokens = ["rustAnalyzer/Indexing", "rustAnalyzer/cachePriming"]
+# quietPeriod = 1000
+# Seconds to wait for the index, zero waits forever.
+# readyTimeout = 600
+# Seconds to wait for a response, zero waits forever.
+# requestTimeout = 30
+# Retries of requests cancelled by the server or with modified content.
+# requestRetries = 3
+
+# [servers.rust.env]
+# RUST_LOG = "error"
+
+# [servers.rust.initializationOptions]
+# cargo = {{ features = "all" }}
+
+# [servers.typescript]
+# command = "typescript-language-server"
+# args = ["--stdio"]
+# extensions = ["ts", "tsx"]
+# readiness = "progress"
+
+# Profiles for references by path, the first matching route is used. Single
+# reference can select a profile with `?server=name` parameter as well.
+# [[routes]]
+# path = "web/**"
+# server = "typescript"
+
+# Defaults for `verify`, `--no-update` and `--no-backup` turn them off.
+[verify]
+update = false
+backup = false
+ignore = []
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::parse(
+            r#"
+graphs = ["docs/graph.json"]
+
+[servers.rust]
+command = "rust-analyzer"
+
+[servers.ts]
+command = "typescript-language-server"
+args = ["--stdio"]
+env = { TSS_LOG = "-level terse" }
+initializationOptions = { preferences = { quotePreference = "single" } }
+requestTimeout = 0
+requestRetries = 1
+
+[[routes]]
+path = "web/**"
+server = "ts"
+
+[verify]
+update = true
+ignore = ["target/**"]
+"#,
+        )
+        .unwrap();
+
+        // No default profile is selected among several.
+        assert!(config.default_server().is_none());
+        let ts = &config.servers["ts"];
+        assert_eq!(ts.env["TSS_LOG"], "-level terse");
+        assert_eq!(
+            ts.initialization_options.as_ref().unwrap()["preferences"]["quotePreference"],
+            "single"
+        );
+        assert_eq!((ts.request_timeout, ts.request_retries), (Some(0), Some(1)));
+        assert_eq!(config.routes[0].server, "ts");
+        assert!(config.verify.update && !config.verify.backup);
+
+        assert!(Config::parse("[[routes]]\npath = \"*\"\nserver = \"missing\"\n").is_err());
+        assert!(Config::parse("unknown = 1\n").is_err());
+    }
+
+    #[test]
+    fn parse_scaffold() {
+        let config = Config::parse(&scaffold(&[
+            "graph.json".into(),
+            "docs/a \"b\".json".into(),
+        ]))
+        .unwrap();
+        let server = config.default_server().unwrap();
+        assert_eq!(server.command, "rust-analyzer");
+        assert_eq!(server.readiness(), crate::settings::Readiness::Tokens);
+        assert_eq!(config.graphs.len(), 2);
+        assert_eq!(config.graphs[1], Path::new("docs/a \"b\".json"));
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashSet;
+
+use serde::Serializer;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::convert::{DEFAULT_SIZE, Rect, data_str, node_rects};
+use crate::graph::{Data, Entry, Graph};
+
+/// Document in JSON Canvas format, as used by Obsidian.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct Canvas {
+    #[serde(default)]
+    pub nodes: Vec<CanvasNode>,
+    #[serde(default)]
+    pub edges: Vec<CanvasEdge>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CanvasNode {
+    pub id: String,
+    #[serde(flatten)]
+    pub kind: NodeKind,
+    #[serde(serialize_with = "serialize_integral")]
+    pub x: f64,
+    #[serde(serialize_with = "serialize_integral")]
+    pub y: f64,
+    #[serde(serialize_with = "serialize_integral")]
+    pub width: f64,
+    #[serde(serialize_with = "serialize_integral")]
+    pub height: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub(crate) enum NodeKind {
+    Text {
+        text: String,
+    },
+    File {
+        file: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        subpath: Option<String>,
+    },
+    Link {
+        url: String,
+    },
+    Group {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CanvasEdge {
+    pub id: String,
+    pub from_node: String,
+    pub to_node: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// Convert graph to JSON Canvas. Compound nodes become groups, `file://` refs
+/// become file nodes, and the rest are text nodes with label and note.
+pub(crate) fn write(graph: &Graph) -> Canvas {
+    let rects = node_rects(graph);
+    let compound: HashSet<&str> = graph
+        .nodes
+        .iter()
+        .filter_map(|node| node.data.parent.as_deref())
+        .collect();
+
+    let mut canvas = Canvas::default();
+    for node in graph.nodes.iter().filter(|node| !node.removed) {
+        let id = node.data.id.as_str();
+        let rect = rects[id];
+        let kind = if compound.contains(id) {
+            NodeKind::Group {
+                label: node.data.label.clone(),
+            }
+        } else if let Some(path) = node
+            .data
+            .r#ref
+            .as_deref()
+            .and_then(|r| r.strip_prefix("file://"))
+        {
+            let (file, subpath) = match path.split_once('#') {
+                Some((file, subpath)) => (file, Some(format!("#{subpath}"))),
+                None => (path, None),
+            };
+            NodeKind::File {
+                file: file.into(),
+                subpath,
+            }
+        } else if let Some(url) = data_str(&node.data, "url") {
+            NodeKind::Link { url: url.into() }
+        } else {
+            NodeKind::Text {
+                text: node_text(&node.data),
+            }
+        };
+
+        canvas.nodes.push(CanvasNode {
+            id: id.into(),
+            kind,
+            x: rect.left().round(),
+            y: rect.top().round(),
+            width: rect.width.round(),
+            height: rect.height.round(),
+            color: data_str(&node.data, "color").map(Into::into),
+        });
+    }
+
+    for edge in graph.edges.iter().filter(|edge| !edge.removed) {
+        let (source, target) = match (&edge.data.source, &edge.data.target) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+        canvas.edges.push(CanvasEdge {
+            id: edge.data.id.clone(),
+            from_node: source.clone(),
+            to_node: target.clone(),
+            label: edge.data.label.clone(),
+            color: data_str(&edge.data, "color").map(Into::into),
+        });
+    }
+    canvas
+}
+
+/// Canvas coordinates are integers, keep them that way where possible.
+fn serialize_integral<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        serializer.serialize_i64(*value as i64)
+    } else {
+        serializer.serialize_f64(*value)
+    }
+}
+
+/// Text node contents: label as a heading followed by the note.
+fn node_text(data: &Data) -> String {
+    match (&data.label, &data.note) {
+        (Some(label), Some(note)) => format!("## {label}\n\n{note}"),
+        (Some(label), None) => label.clone(),
+        (None, Some(note)) => note.clone(),
+        (None, None) => data.id.clone(),
+    }
+}
+
+/// Convert JSON Canvas to graph. Canvas groups don't list their members, so
+/// nodes are nested into the smallest group which contains them.
+pub(crate) fn read(canvas: &Canvas) -> Graph {
+    let rects: Vec<Rect> = canvas
+        .nodes
+        .iter()
+        .map(|node| Rect {
+            x: node.x + node.width / 2.0,
+            y: node.y + node.height / 2.0,
+            width: node.width,
+            height: node.height,
+        })
+        .collect();
+    let contains = |outer: &Rect, inner: &Rect| {
+        outer.left() <= inner.left()
+            && outer.top() <= inner.top()
+            && outer.right() >= inner.right()
+            && outer.bottom() >= inner.bottom()
+    };
+
+    let mut graph = Graph::default();
+    for (index, node) in canvas.nodes.iter().enumerate() {
+        let rect = &rects[index];
+        let mut data = Data::new(&node.id);
+        data.parent = canvas
+            .nodes
+            .iter()
+            .zip(&rects)
+            .enumerate()
+            .filter(|(other, (group, outer))| {
+                *other != index
+                    && matches!(group.kind, NodeKind::Group { .. })
+                    && contains(outer, rect)
+                    && !(contains(rect, outer) && *other > index)
+            })
+            .min_by(|(_, (_, a)), (_, (_, b))| {
+                (a.width * a.height).total_cmp(&(b.width * b.height))
+            })
+            .map(|(_, (group, _))| group.id.clone());
+
+        match &node.kind {
+            NodeKind::Text { text } => read_text(&mut data, text),
+            NodeKind::File { file, subpath } => {
+                data.r#ref = Some(format!("file://{file}{}", subpath.as_deref().unwrap_or("")));
+            }
+            NodeKind::Link { url } => {
+                data.data.insert("url".into(), url.as_str().into());
+            }
+            NodeKind::Group { label } => data.label = label.clone(),
+        }
+        if let Some(color) = &node.color {
+            data.data.insert("color".into(), color.as_str().into());
+        }
+        // Editor nodes are square, other proportions are not carried over.
+        if !matches!(node.kind, NodeKind::Group { .. })
+            && node.width == node.height
+            && node.width != DEFAULT_SIZE
+        {
+            let size = match node.width.fract() {
+                0.0 => (node.width as i64).into(),
+                _ => node.width.into(),
+            };
+            data.data.insert("size".into(), size);
+        }
+
+        let mut entry = Entry::node(data);
+        entry.position.x = rect.x;
+        entry.position.y = rect.y;
+        graph.nodes.push(entry);
+    }
+
+    for edge in &canvas.edges {
+        let mut data = Data::link(&edge.id, &edge.from_node, &edge.to_node);
+        data.label = edge.label.clone();
+        if let Some(color) = &edge.color {
+            data.data.insert("color".into(), color.as_str().into());
+        }
+        graph.edges.push(Entry::edge(data));
+    }
+    graph
+}
+
+/// Split text node into label and note: a leading heading or a single line
+/// becomes the label.
+fn read_text(data: &mut Data, text: &str) {
+    let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+    let heading = first.trim_start_matches('#');
+    if heading.len() < first.len() && heading.starts_with(' ') {
+        data.label = Some(heading.trim().into());
+        let note = rest.trim();
+        data.note = (!note.is_empty()).then(|| note.into());
+    } else if rest.is_empty() {
+        data.label = Some(text.into());
+    } else {
+        data.note = Some(text.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut graph = Graph::default();
+        let mut group = Data::new("group");
+        group.label = Some("Design".into());
+        graph.nodes.push(Entry::node(group));
+
+        let nodes = [
+            (
+                "idea",
+                Some("Idea"),
+                Some("Details\non two lines"),
+                None,
+                0.0,
+            ),
+            ("spec", None, None, Some("file://docs/spec.md#Scope"), 100.0),
+            ("todo", Some("Todo"), None, None, 200.0),
+        ];
+        for (id, label, note, r#ref, x) in nodes {
+            let mut data = Data::new(id);
+            data.parent = Some("group".into());
+            data.label = label.map(Into::into);
+            data.note = note.map(Into::into);
+            data.r#ref = r#ref.map(Into::into);
+            let mut node = Entry::node(data);
+            node.position.x = x;
+            node.position.y = 50.0;
+            graph.nodes.push(node);
+        }
+        let mut outside = Data::new("site");
+        outside
+            .data
+            .insert("url".into(), "https://example.com".into());
+        outside.data.insert("size".into(), 60.into());
+        let mut outside = Entry::node(outside);
+        outside.position.y = 300.0;
+        graph.nodes.push(outside);
+
+        let mut edge = Data::link("idea->spec", "idea", "spec");
+        edge.label = Some("described in".into());
+        graph.edges.push(Entry::edge(edge));
+
+        let canvas = write(&graph);
+        let json = serde_json::to_value(&canvas).unwrap();
+        assert_eq!(
+            json["nodes"][1],
+            serde_json::json!({
+                "id": "idea", "type": "text", "text": "## Idea\n\nDetails\non two lines",
+                "x": -15, "y": 35, "width": 30, "height": 30
+            })
+        );
+        assert_eq!(json["nodes"][2]["type"], "file");
+        assert_eq!(json["nodes"][2]["subpath"], "#Scope");
+        assert_eq!(json["nodes"][0]["type"], "group");
+        assert_eq!(json["edges"][0]["fromNode"], "idea");
+
+        let again = read(&serde_json::from_value(json).unwrap());
+        // Groups are placed at the center of their children.
+        graph.nodes[0].position.x = 100.0;
+        graph.nodes[0].position.y = 50.0;
+        assert_eq!(
+            serde_json::to_value(&again).unwrap(),
+            serde_json::to_value(&graph).unwrap()
+        );
+    }
+}
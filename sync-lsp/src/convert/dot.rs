@@ -0,0 +1,994 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use anyhow::{Context as _, bail};
+use indexmap::IndexMap;
+
+use crate::convert::{children, data_str, location_url, roots};
+use crate::graph::{Data, Entry, Graph};
+use unwrap_or::unwrap_some_or;
+
+/// List of DOT attributes attached to a statement.
+pub(crate) type Attrs = IndexMap<String, String>;
+
+/// Flattened contents of DOT graph.
+#[derive(Default)]
+pub(crate) struct DotGraph {
+    pub nodes: IndexMap<String, Attrs>,
+    pub edges: Vec<DotEdge>,
+    /// Cluster subgraphs by name.
+    pub clusters: IndexMap<String, DotCluster>,
+    /// Cluster which the node was first declared in.
+    pub node_clusters: HashMap<String, String>,
+}
+
+pub(crate) struct DotEdge {
+    pub source: String,
+    pub target: String,
+    pub attrs: Attrs,
+}
+
+#[derive(Default)]
+pub(crate) struct DotCluster {
+    pub parent: Option<String>,
+    pub attrs: Attrs,
+}
+
+impl DotGraph {
+    /// Parse DOT source. Only the subset which is needed to extract nodes,
+    /// edges, clusters and their attributes is supported.
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            graph: Self::default(),
+            scopes: vec![Scope::default()],
+            mentions: Vec::new(),
+            anonymous: 0,
+        };
+        parser.parse_graph()?;
+        Ok(parser.graph)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Id(String),
+    Arrow,
+    OpenBrace,
+    CloseBrace,
+    OpenBracket,
+    CloseBracket,
+    Equals,
+    Colon,
+    Plus,
+    Separator,
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    graph: DotGraph,
+    scopes: Vec<Scope>,
+    /// Nodes mentioned within each of the open subgraphs.
+    mentions: Vec<Vec<String>>,
+    anonymous: usize,
+}
+
+/// Edge statement part: single node or all nodes mentioned within subgraph.
+enum Operand {
+    Node(String),
+    Subgraph(Vec<String>),
+}
+
+impl Operand {
+    fn into_nodes(self) -> Vec<String> {
+        match self {
+            Self::Node(id) => vec![id],
+            Self::Subgraph(nodes) => nodes,
+        }
+    }
+}
+
+/// Attribute defaults and cluster of the current `{ ... }` block.
+#[derive(Clone, Default)]
+struct Scope {
+    cluster: Option<String>,
+    node: Attrs,
+    edge: Attrs,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> anyhow::Result<()> {
+        match self.next() {
+            Some(token) if *token == expected => Ok(()),
+            token => bail!("Expected {expected:?}, found {token:?}"),
+        }
+    }
+
+    fn expect_id(&mut self) -> anyhow::Result<String> {
+        match self.next() {
+            Some(Token::Id(id)) => Ok(id.clone()),
+            token => bail!("Expected identifier, found {token:?}"),
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Id(id)) if id.eq_ignore_ascii_case(keyword))
+    }
+
+    /// `[strict] (graph | digraph) [ID] '{' stmt_list '}'`
+    fn parse_graph(&mut self) -> anyhow::Result<()> {
+        if self.peek_keyword("strict") {
+            self.pos += 1;
+        }
+        if !self.peek_keyword("graph") && !self.peek_keyword("digraph") {
+            bail!("DOT source should start with 'graph' or 'digraph'");
+        }
+        self.pos += 1;
+        if let Some(Token::Id(_)) = self.peek() {
+            self.pos += 1;
+        }
+        self.expect(Token::OpenBrace)?;
+        self.parse_statements()?;
+        self.expect(Token::CloseBrace)
+    }
+
+    fn parse_statements(&mut self) -> anyhow::Result<()> {
+        loop {
+            match self.peek() {
+                Some(Token::CloseBrace) | None => return Ok(()),
+                Some(Token::Separator) => self.pos += 1,
+                _ => self.parse_statement()?,
+            }
+        }
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("Root scope is always present")
+    }
+
+    fn parse_statement(&mut self) -> anyhow::Result<()> {
+        for keyword in ["graph", "node", "edge"] {
+            if self.peek_keyword(keyword)
+                && self.tokens.get(self.pos + 1) == Some(&Token::OpenBracket)
+            {
+                self.pos += 1;
+                let attrs = self.parse_attrs()?;
+                match keyword {
+                    "graph" => self.set_cluster_attrs(attrs),
+                    "node" => self.scope().node.extend(attrs),
+                    _ => self.scope().edge.extend(attrs),
+                }
+                return Ok(());
+            }
+        }
+
+        if let Some(Token::Id(key)) = self.peek()
+            && self.tokens.get(self.pos + 1) == Some(&Token::Equals)
+        {
+            let key = key.clone();
+            self.pos += 2;
+            let value = self.expect_id()?;
+            self.set_cluster_attrs(Attrs::from([(key, value)]));
+            return Ok(());
+        }
+
+        let mut chain = vec![self.parse_operand()?];
+        while self.peek() == Some(&Token::Arrow) {
+            self.pos += 1;
+            chain.push(self.parse_operand()?);
+        }
+        let attrs = self.parse_attrs()?;
+
+        if let [Operand::Node(id)] = chain.as_slice() {
+            let id = id.clone();
+            self.add_node(&id, &attrs);
+            return Ok(());
+        }
+        let chain: Vec<Vec<String>> = chain.into_iter().map(Operand::into_nodes).collect();
+
+        let mut edge_attrs = self.scope().edge.clone();
+        edge_attrs.extend(attrs);
+        for pair in chain.windows(2) {
+            for source in &pair[0] {
+                for target in &pair[1] {
+                    self.graph.edges.push(DotEdge {
+                        source: source.clone(),
+                        target: target.clone(),
+                        attrs: edge_attrs.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Node id or subgraph block.
+    fn parse_operand(&mut self) -> anyhow::Result<Operand> {
+        if !self.peek_keyword("subgraph") && self.peek() != Some(&Token::OpenBrace) {
+            let id = self.parse_node_id()?;
+            self.add_node(&id, &Attrs::new());
+            return Ok(Operand::Node(id));
+        }
+
+        let name = if self.peek_keyword("subgraph") {
+            self.pos += 1;
+            match self.peek() {
+                Some(Token::Id(name)) => {
+                    let name = name.clone();
+                    self.pos += 1;
+                    Some(name)
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+        let name = name.unwrap_or_else(|| {
+            self.anonymous += 1;
+            format!("%{}", self.anonymous)
+        });
+
+        let mut scope = self.scope().clone();
+        if name.starts_with("cluster") {
+            let parent = scope.cluster.clone();
+            self.graph
+                .clusters
+                .entry(name.clone())
+                .or_insert_with(|| DotCluster {
+                    parent,
+                    ..Default::default()
+                });
+            scope.cluster = Some(name);
+        }
+        self.scopes.push(scope);
+        self.mentions.push(Vec::new());
+
+        self.expect(Token::OpenBrace)?;
+        self.parse_statements()?;
+        self.expect(Token::CloseBrace)?;
+        self.scopes.pop();
+
+        let nodes = self.mentions.pop().unwrap_or_default();
+        if let Some(outer) = self.mentions.last_mut() {
+            outer.extend(nodes.iter().cloned());
+        }
+        Ok(Operand::Subgraph(nodes))
+    }
+
+    /// Graph attributes are only kept for clusters.
+    fn set_cluster_attrs(&mut self, attrs: Attrs) {
+        let cluster = unwrap_some_or!(self.scope().cluster.clone(), { return });
+        if let Some(cluster) = self.graph.clusters.get_mut(&cluster) {
+            cluster.attrs.extend(attrs);
+        }
+    }
+
+    /// Register the node if it wasn't seen before and merge the attributes.
+    fn add_node(&mut self, id: &str, attrs: &Attrs) {
+        if let Some(mentions) = self.mentions.last_mut()
+            && !mentions.iter().any(|m| m == id)
+        {
+            mentions.push(id.into());
+        }
+
+        let scope = self.scopes.last().expect("Root scope is always present");
+        if !self.graph.nodes.contains_key(id) {
+            self.graph.nodes.insert(id.into(), scope.node.clone());
+            if let Some(cluster) = &scope.cluster {
+                self.graph.node_clusters.insert(id.into(), cluster.clone());
+            }
+        }
+        if let Some(node) = self.graph.nodes.get_mut(id) {
+            node.extend(attrs.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+    }
+
+    /// `ID [':' port [':' compass]]`, port part is ignored.
+    fn parse_node_id(&mut self) -> anyhow::Result<String> {
+        let id = self.expect_id()?;
+        while self.peek() == Some(&Token::Colon) {
+            self.pos += 1;
+            self.expect_id()?;
+        }
+        Ok(id)
+    }
+
+    /// Zero or more `'[' a_list ']'` groups.
+    fn parse_attrs(&mut self) -> anyhow::Result<Attrs> {
+        let mut attrs = Attrs::new();
+        while self.peek() == Some(&Token::OpenBracket) {
+            self.pos += 1;
+            loop {
+                match self.peek() {
+                    Some(Token::CloseBracket) => {
+                        self.pos += 1;
+                        break;
+                    }
+                    Some(Token::Separator) => self.pos += 1,
+                    _ => {
+                        let key = self.expect_id()?;
+                        self.expect(Token::Equals)?;
+                        let value = self.expect_id()?;
+                        attrs.insert(key, value);
+                    }
+                }
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+/// Split DOT source into tokens, dropping comments and whitespace.
+fn tokenize(source: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line_start = true;
+            continue;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+        // Preprocessor output lines.
+        if c == '#' && line_start {
+            for c in chars.by_ref() {
+                if c == '\n' {
+                    break;
+                }
+            }
+            continue;
+        }
+        line_start = false;
+
+        match c {
+            '{' => tokens.push(Token::OpenBrace),
+            '}' => tokens.push(Token::CloseBrace),
+            '[' => tokens.push(Token::OpenBracket),
+            ']' => tokens.push(Token::CloseBracket),
+            '=' => tokens.push(Token::Equals),
+            ':' => tokens.push(Token::Colon),
+            ';' | ',' => tokens.push(Token::Separator),
+            '-' if matches!(chars.peek(), Some('>' | '-')) => {
+                chars.next();
+                tokens.push(Token::Arrow);
+            }
+            '+' => tokens.push(Token::Plus),
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line_start = true;
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                loop {
+                    let c = chars.next().context("Unterminated comment")?;
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next().context("Unterminated string")? {
+                        '"' => break,
+                        '\\' => match chars.next().context("Unterminated string")? {
+                            '"' => value.push('"'),
+                            '\n' => {}
+                            c => {
+                                value.push('\\');
+                                value.push(c);
+                            }
+                        },
+                        c => value.push(c),
+                    }
+                }
+                // Concatenation: "a" + "b".
+                if tokens.last() == Some(&Token::Plus) {
+                    tokens.pop();
+                    if let Some(Token::Id(prev)) = tokens.last_mut() {
+                        prev.push_str(&value);
+                        continue;
+                    }
+                }
+                tokens.push(Token::Id(value));
+            }
+            '<' => {
+                let mut value = String::new();
+                let mut depth = 1;
+                loop {
+                    let c = chars.next().context("Unterminated HTML string")?;
+                    match c {
+                        '<' => depth += 1,
+                        '>' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    value.push(c);
+                }
+                tokens.push(Token::Id(value));
+            }
+            c if is_id_char(c) || c == '-' => {
+                let mut value = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if !is_id_char(c) {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Id(value));
+            }
+            c => bail!("Unexpected character in DOT source: '{c}'"),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || !c.is_ascii()
+}
+
+/// Convert DOT source into graph. Clusters become compound nodes, and `URL`
+/// attributes which point to source files become `file://` or `lsp://` refs.
+pub(crate) fn read(source: &str) -> anyhow::Result<Graph> {
+    let dot = DotGraph::parse(source)?;
+    let mut graph = Graph::default();
+    let mut taken: HashSet<String> = dot.nodes.keys().cloned().collect();
+
+    let mut cluster_ids = HashMap::new();
+    for name in dot.clusters.keys() {
+        let short = name
+            .strip_prefix("cluster_")
+            .or_else(|| name.strip_prefix("cluster"))
+            .unwrap_or(name);
+        let id = if short.is_empty() || taken.contains(short) {
+            name.clone()
+        } else {
+            short.to_string()
+        };
+        taken.insert(id.clone());
+        cluster_ids.insert(name.as_str(), id);
+    }
+
+    for (name, cluster) in &dot.clusters {
+        let mut data = Data::new(&cluster_ids[name.as_str()]);
+        data.parent = cluster
+            .parent
+            .as_deref()
+            .and_then(|parent| cluster_ids.get(parent).cloned());
+        entry_data(&cluster.attrs, name, &mut data);
+        graph.nodes.push(Entry::node(data));
+    }
+
+    for (id, attrs) in &dot.nodes {
+        let mut data = Data::new(id);
+        data.parent = dot
+            .node_clusters
+            .get(id)
+            .and_then(|cluster| cluster_ids.get(cluster.as_str()).cloned());
+        entry_data(attrs, id, &mut data);
+        if let Some(shape) = attrs.get("shape") {
+            let rounded = attrs
+                .get("style")
+                .is_some_and(|style| style.contains("rounded"));
+            if let Some(shape) = editor_shape(shape, rounded) {
+                data.data.insert("shape".into(), shape.into());
+            }
+        }
+
+        let mut node = Entry::node(data);
+        if let Some((x, y)) = attrs.get("pos").and_then(|pos| parse_pos(pos)) {
+            // Graphviz Y axis points upwards.
+            node.position.x = x;
+            node.position.y = -y;
+        }
+        graph.nodes.push(node);
+    }
+
+    for edge in &dot.edges {
+        let source = edge
+            .attrs
+            .get("ltail")
+            .and_then(|cluster| cluster_ids.get(cluster.as_str()))
+            .unwrap_or(&edge.source);
+        let target = edge
+            .attrs
+            .get("lhead")
+            .and_then(|cluster| cluster_ids.get(cluster.as_str()))
+            .unwrap_or(&edge.target);
+
+        let mut id = format!("{source}->{target}");
+        let mut suffix = 1;
+        while !taken.insert(id.clone()) {
+            suffix += 1;
+            id = format!("{source}->{target}-{suffix}");
+        }
+        let mut data = Data::link(id, source, target);
+        entry_data(&edge.attrs, "", &mut data);
+        graph.edges.push(Entry::edge(data));
+    }
+
+    Ok(graph)
+}
+
+/// Fill label, note and reference fields from DOT attributes.
+fn entry_data(attrs: &Attrs, name: &str, data: &mut Data) {
+    if let Some(label) = attrs.get("label") {
+        let label = unescape(label, name);
+        if !label.is_empty() {
+            data.label = Some(label);
+        }
+    }
+    data.note = attrs.get("comment").cloned();
+
+    // Reference written by the exporter.
+    if let Some(r#ref) = attrs.get("ref") {
+        data.r#ref = Some(r#ref.clone());
+        data.location = attrs.get("tooltip").cloned();
+        data.valid = attrs.get("valid").and_then(|valid| valid.parse().ok());
+        return;
+    }
+
+    if let Some(tooltip) = attrs.get("tooltip") {
+        data.note.get_or_insert_with(|| unescape(tooltip, name));
+    }
+    if let Some(url) = attrs.get("URL").or_else(|| attrs.get("href")) {
+        match url_ref(url) {
+            Some(r#ref) => data.r#ref = Some(r#ref),
+            None => {
+                data.data.insert("url".into(), url.clone().into());
+            }
+        }
+    }
+}
+
+/// Convert URL which looks like source path into reference: `path#symbol`
+/// becomes `lsp://` reference, other paths become `file://` references.
+fn url_ref(url: &str) -> Option<String> {
+    let url = match url.strip_prefix("file://") {
+        Some(path) => path,
+        None if url.contains("://") || url.starts_with("mailto:") => return None,
+        None => url,
+    };
+    let (path, fragment) = match url.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (url, None),
+    };
+    // Drop `:line` suffix.
+    let path = match path.rsplit_once(':') {
+        Some((path, line)) if line.parse::<u32>().is_ok() => path,
+        _ => path,
+    };
+
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let extension = file.rsplit_once('.').map(|(_, ext)| ext);
+    let is_source = !path.chars().any(char::is_whitespace)
+        && extension.is_some_and(|ext| !ext.is_empty() && ext.chars().all(char::is_alphanumeric));
+    if !is_source {
+        return None;
+    }
+
+    let is_line_anchor = |fragment: &str| {
+        let digits = fragment.strip_prefix('L').unwrap_or(fragment);
+        digits
+            .split('-')
+            .all(|part| !part.is_empty() && part.trim_start_matches('L').parse::<u32>().is_ok())
+    };
+    match fragment {
+        Some(symbol) if !symbol.is_empty() && !is_line_anchor(symbol) => {
+            Some(format!("lsp://{path}#{symbol}"))
+        }
+        _ => Some(format!("file://{path}")),
+    }
+}
+
+/// Resolve DOT escape sequences in labels.
+fn unescape(text: &str, name: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'l' | 'r') => out.push('\n'),
+            Some('N') => out.push_str(name),
+            Some('G' | 'E' | 'T' | 'H') => {}
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out.trim_end_matches('\n').into()
+}
+
+/// Parse `pos` attribute of laid out graph: `x,y` with optional `!` suffix.
+fn parse_pos(pos: &str) -> Option<(f64, f64)> {
+    let (x, y) = pos.trim_end_matches('!').split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+/// Map Graphviz shape to editor node shape. Ellipse is the default for both.
+fn editor_shape(shape: &str, rounded: bool) -> Option<&'static str> {
+    Some(match shape {
+        "box" | "rect" | "rectangle" | "square" if rounded => "round-rectangle",
+        "box" | "rect" | "rectangle" | "square" => "rectangle",
+        "circle" | "oval" | "ellipse" | "point" | "doublecircle" => return None,
+        "triangle" => "triangle",
+        "diamond" => "diamond",
+        "pentagon" => "pentagon",
+        "hexagon" => "hexagon",
+        "septagon" => "heptagon",
+        "octagon" | "doubleoctagon" | "tripleoctagon" => "octagon",
+        "star" => "star",
+        "parallelogram" => "rhomboid",
+        "cylinder" => "barrel",
+        "cds" => "tag",
+        "invtriangle" => "vee",
+        _ => return None,
+    })
+}
+
+/// Produce DOT source from the graph. Compound nodes are written as clusters,
+/// resolved reference locations are kept in `URL` and `tooltip` attributes.
+pub(crate) fn write(graph: &Graph) -> String {
+    let children = children(graph);
+    let mut out = String::from("digraph islands {\n    compound=true;\n");
+
+    for index in roots(graph) {
+        write_node(&mut out, graph, &children, index, 1);
+    }
+
+    for edge in graph.edges.iter().filter(|edge| !edge.removed) {
+        let (source, target) = match (&edge.data.source, &edge.data.target) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+        let mut attrs = Attrs::new();
+        let source = endpoint(graph, &children, source, "ltail", &mut attrs);
+        let target = endpoint(graph, &children, target, "lhead", &mut attrs);
+        entry_attrs(&edge.data, &mut attrs);
+        let _ = writeln!(
+            out,
+            "    {} -> {}{};",
+            quote(&source),
+            quote(&target),
+            format_attrs(&attrs)
+        );
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+fn write_node(
+    out: &mut String,
+    graph: &Graph,
+    children: &HashMap<&str, Vec<usize>>,
+    index: usize,
+    depth: usize,
+) {
+    let node = &graph.nodes[index];
+    let indent = "    ".repeat(depth);
+    let mut attrs = Attrs::new();
+    entry_attrs(&node.data, &mut attrs);
+
+    let nested = match children.get(node.data.id.as_str()) {
+        Some(nested) if depth <= graph.nodes.len() => nested,
+        _ => {
+            if let Some(shape) = data_str(&node.data, "shape") {
+                let (shape, rounded) = dot_shape(shape);
+                attrs.insert("shape".into(), shape.into());
+                if rounded {
+                    attrs.insert("style".into(), "rounded".into());
+                }
+            }
+            let _ = writeln!(
+                out,
+                "{indent}{}{};",
+                quote(&node.data.id),
+                format_attrs(&attrs)
+            );
+            return;
+        }
+    };
+
+    let _ = writeln!(
+        out,
+        "{indent}subgraph {} {{",
+        quote(&cluster_name(&node.data.id))
+    );
+    for (key, value) in &attrs {
+        let _ = writeln!(out, "{indent}    {key}={};", quote(value));
+    }
+    for &child in nested {
+        write_node(out, graph, children, child, depth + 1);
+    }
+    let _ = writeln!(out, "{indent}}}");
+}
+
+/// Edges can't be attached to clusters directly, so compound endpoints are
+/// replaced with the first leaf node inside and clipped at the cluster border.
+fn endpoint(
+    graph: &Graph,
+    children: &HashMap<&str, Vec<usize>>,
+    id: &str,
+    clip: &str,
+    attrs: &mut Attrs,
+) -> String {
+    let mut current = id;
+    for _ in 0..graph.nodes.len() {
+        match children.get(current).and_then(|nested| nested.first()) {
+            Some(&child) => current = &graph.nodes[child].data.id,
+            None => break,
+        }
+    }
+    if current != id {
+        attrs.insert(clip.into(), cluster_name(id));
+    }
+    current.into()
+}
+
+/// Label, note and reference attributes shared by nodes, clusters and edges.
+fn entry_attrs(data: &Data, attrs: &mut Attrs) {
+    if let Some(label) = &data.label {
+        attrs.insert("label".into(), label.clone());
+    }
+    if let Some(note) = &data.note {
+        attrs.insert("comment".into(), note.clone());
+    }
+    let r#ref = match &data.r#ref {
+        Some(r#ref) => r#ref,
+        None => return,
+    };
+    attrs.insert("ref".into(), r#ref.clone());
+    match &data.location {
+        Some(location) => {
+            attrs.insert("URL".into(), location_url(location));
+            attrs.insert("tooltip".into(), location.clone());
+        }
+        None if r#ref.starts_with("file:") => {
+            let path = r#ref.trim_start_matches("file:").trim_start_matches("//");
+            attrs.insert("URL".into(), path.into());
+            attrs.insert("tooltip".into(), path.into());
+        }
+        None => {}
+    }
+    if let Some(valid) = data.valid {
+        attrs.insert("valid".into(), valid.to_string());
+    }
+}
+
+fn cluster_name(id: &str) -> String {
+    format!("cluster_{id}")
+}
+
+/// Map editor node shape to Graphviz shape, along with the rounded style flag.
+fn dot_shape(shape: &str) -> (&str, bool) {
+    match shape {
+        "rectangle" => ("box", false),
+        "round-rectangle" | "bottom-round-rectangle" => ("box", true),
+        "cut-rectangle" => ("octagon", false),
+        "rhomboid" | "right-rhomboid" => ("parallelogram", false),
+        "barrel" => ("cylinder", false),
+        "round-triangle" => ("triangle", false),
+        "round-diamond" => ("diamond", false),
+        "round-pentagon" => ("pentagon", false),
+        "round-hexagon" | "concave-hexagon" => ("hexagon", false),
+        "round-heptagon" => ("septagon", false),
+        "heptagon" => ("septagon", false),
+        "round-octagon" => ("octagon", false),
+        "tag" | "round-tag" => ("cds", false),
+        "vee" => ("invtriangle", false),
+        shape => (shape, false),
+    }
+}
+
+fn format_attrs(attrs: &Attrs) -> String {
+    if attrs.is_empty() {
+        return String::new();
+    }
+    let attrs: Vec<String> = attrs
+        .iter()
+        .map(|(key, value)| format!("{key}={}", quote(value)))
+        .collect();
+    format!(" [{}]", attrs.join(", "))
+}
+
+/// Quote DOT identifier, newlines become `\n` escapes.
+fn quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_crate_graph() {
+        let graph = DotGraph::parse(
+            r#"digraph rust_analyzer_crate_graph {
+    _0[label="core"][shape="box"];
+    _1[label="alloc"][shape="box"];
+    _2[label="my_crate"][shape="box"];
+    _1 -> _0[label=""];
+    _2 -> _1 -> _0;
+}"#,
+        )
+        .unwrap();
+
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.nodes["_2"]["label"], "my_crate");
+        assert_eq!(graph.nodes["_0"]["shape"], "box");
+        assert_eq!(graph.edges.len(), 3);
+        assert_eq!(graph.edges[1].source, "_2");
+        assert_eq!(graph.edges[2].target, "_0");
+    }
+
+    #[test]
+    fn write_clusters() {
+        let mut graph = Graph::default();
+        let mut data = Data::new("group");
+        data.label = Some("Group".into());
+        graph.nodes.push(Entry::node(data));
+
+        let mut data = Data::new("main");
+        data.parent = Some("group".into());
+        data.label = Some("main \"fn\"".into());
+        data.note = Some("Entry point".into());
+        data.r#ref = Some("lsp://src/main.rs#main".into());
+        data.location = Some("src/main.rs:42".into());
+        data.valid = Some(true);
+        data.data.insert("shape".into(), "round-rectangle".into());
+        graph.nodes.push(Entry::node(data));
+
+        graph.nodes.push(Entry::node(Data::new("other")));
+        graph
+            .edges
+            .push(Entry::edge(Data::link("e", "other", "group")));
+
+        assert_eq!(
+            write(&graph),
+            r#"digraph islands {
+    compound=true;
+    subgraph "cluster_group" {
+        label="Group";
+        "main" [label="main \"fn\"", comment="Entry point", ref="lsp://src/main.rs#main", URL="src/main.rs#L42", tooltip="src/main.rs:42", valid="true", shape="box", style="rounded"];
+    }
+    "other";
+    "other" -> "main" [lhead="cluster_group"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn read_clusters() {
+        let graph = read(
+            r#"digraph legacy {
+    node [shape=box];
+    subgraph cluster_core {
+        label = "Core";
+        parser [label="Parser\nmodule", URL="src/parser.rs#Parser"];
+        subgraph cluster_io {
+            graph [label="IO"];
+            reader [URL="src/io/reader.rs#L10", pos="100,50!"];
+        }
+    }
+    docs [shape=ellipse, URL="https://example.com", tooltip="Documentation"];
+    parser -> reader [label="reads"];
+    docs -> parser [lhead=cluster_core];
+    { docs parser } -> reader;
+}"#,
+        )
+        .unwrap();
+
+        let node = |id: &str| graph.nodes.iter().find(|n| n.data.id == id).unwrap();
+        assert_eq!(node("core").data.label.as_deref(), Some("Core"));
+        assert_eq!(node("io").data.parent.as_deref(), Some("core"));
+        assert_eq!(node("io").data.label.as_deref(), Some("IO"));
+
+        let parser = node("parser");
+        assert_eq!(parser.data.parent.as_deref(), Some("core"));
+        assert_eq!(parser.data.label.as_deref(), Some("Parser\nmodule"));
+        assert_eq!(
+            parser.data.r#ref.as_deref(),
+            Some("lsp://src/parser.rs#Parser")
+        );
+        assert_eq!(data_str(&parser.data, "shape"), Some("rectangle"));
+
+        let reader = node("reader");
+        assert_eq!(reader.data.parent.as_deref(), Some("io"));
+        assert_eq!(
+            reader.data.r#ref.as_deref(),
+            Some("file://src/io/reader.rs")
+        );
+        assert_eq!((reader.position.x, reader.position.y), (100.0, -50.0));
+
+        let docs = node("docs");
+        assert_eq!(docs.data.parent, None);
+        assert_eq!(docs.data.r#ref, None);
+        assert_eq!(docs.data.note.as_deref(), Some("Documentation"));
+        assert_eq!(data_str(&docs.data, "shape"), None);
+
+        let edges: Vec<(&str, &str)> = graph
+            .edges
+            .iter()
+            .map(|e| {
+                (
+                    e.data.source.as_deref().unwrap(),
+                    e.data.target.as_deref().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                ("parser", "reader"),
+                ("docs", "core"),
+                ("docs", "reader"),
+                ("parser", "reader")
+            ]
+        );
+        assert_eq!(graph.edges[0].data.label.as_deref(), Some("reads"));
+        assert_ne!(graph.edges[0].data.id, graph.edges[3].data.id);
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let source = r#"digraph {
+    subgraph cluster_group { label="Group"; a [label="A", ref="lsp://src/a.rs#A", tooltip="src/a.rs:3", valid="true"]; }
+    b;
+    b -> a [label="uses"];
+}"#;
+        let graph = read(source).unwrap();
+        let again = read(&write(&graph)).unwrap();
+
+        assert_eq!(write(&graph), write(&again));
+        let a = again.nodes.iter().find(|n| n.data.id == "a").unwrap();
+        assert_eq!(a.data.parent.as_deref(), Some("group"));
+        assert_eq!(a.data.location.as_deref(), Some("src/a.rs:3"));
+        assert_eq!(a.data.valid, Some(true));
+    }
+}
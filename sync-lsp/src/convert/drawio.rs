@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use crate::convert::{Rect, children, data_str, escape_xml, location_url, node_rects, num, roots};
+use crate::graph::{Data, Graph};
+
+/// Id of the root cell and of the default layer which holds the diagram.
+const ROOT_ID: &str = "islands-root";
+const LAYER_ID: &str = "islands-layer";
+
+const INVALID_COLOR: &str = "#990000";
+const UNCHECKED_COLOR: &str = "#ffec99";
+
+/// Convert graph to uncompressed draw.io document. Compound nodes become
+/// containers, references become links, note and doc become tooltips.
+pub(crate) fn write(graph: &Graph) -> String {
+    let rects = node_rects(graph);
+    let children = children(graph);
+
+    let mut out = String::from(concat!(
+        "<mxfile host=\"islands\">\n",
+        "  <diagram id=\"islands\" name=\"Islands\">\n",
+        "    <mxGraphModel grid=\"1\" gridSize=\"10\" guides=\"1\" tooltips=\"1\" connect=\"1\" arrows=\"1\" page=\"0\">\n",
+        "      <root>\n",
+    ));
+    let _ = writeln!(out, "        <mxCell id=\"{ROOT_ID}\"/>");
+    let _ = writeln!(
+        out,
+        "        <mxCell id=\"{LAYER_ID}\" parent=\"{ROOT_ID}\"/>"
+    );
+
+    for index in roots(graph) {
+        write_node(&mut out, graph, &rects, &children, index, None, 0);
+    }
+
+    for edge in graph.edges.iter().filter(|edge| !edge.removed) {
+        let (source, target) = match (&edge.data.source, &edge.data.target) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+        let style = "edgeStyle=none;html=0;endArrow=classic;";
+        let cell = format!(
+            "edge=\"1\" parent=\"{LAYER_ID}\" source=\"{}\" target=\"{}\" style=\"{style}\"",
+            attr(source),
+            attr(target)
+        );
+        write_cell(
+            &mut out,
+            &edge.data,
+            &cell,
+            "<mxGeometry relative=\"1\" as=\"geometry\"/>",
+        );
+    }
+
+    out.push_str("      </root>\n    </mxGraphModel>\n  </diagram>\n</mxfile>\n");
+    out
+}
+
+fn write_node(
+    out: &mut String,
+    graph: &Graph,
+    rects: &HashMap<&str, Rect>,
+    children: &HashMap<&str, Vec<usize>>,
+    index: usize,
+    parent: Option<&Rect>,
+    depth: usize,
+) {
+    let node = &graph.nodes[index];
+    let rect = match rects.get(node.data.id.as_str()) {
+        Some(rect) => rect,
+        None => return,
+    };
+    let nested = children
+        .get(node.data.id.as_str())
+        .filter(|_| depth <= graph.nodes.len());
+
+    let mut style = match nested {
+        Some(_) => String::from(
+            "rounded=0;html=0;whiteSpace=wrap;container=1;collapsible=0;verticalAlign=bottom;labelPosition=center;verticalLabelPosition=top;fillColor=none;",
+        ),
+        None => format!(
+            "{};html=0;verticalAlign=top;labelPosition=center;verticalLabelPosition=bottom;",
+            drawio_shape(data_str(&node.data, "shape").unwrap_or("ellipse"))
+        ),
+    };
+    match node.data.valid {
+        Some(false) => {
+            let _ = write!(style, "strokeColor={INVALID_COLOR};strokeWidth=3;");
+        }
+        None if node.data.r#ref.is_some() => {
+            let _ = write!(style, "strokeColor={UNCHECKED_COLOR};strokeWidth=3;");
+        }
+        _ => {}
+    }
+
+    // Child geometry is relative to the container.
+    let (left, top) = match parent {
+        Some(parent) => (rect.left() - parent.left(), rect.top() - parent.top()),
+        None => (rect.left(), rect.top()),
+    };
+    let parent_id = match (&node.data.parent, parent) {
+        (Some(id), Some(_)) => attr(id),
+        _ => LAYER_ID.into(),
+    };
+    let cell = format!("vertex=\"1\" parent=\"{parent_id}\" style=\"{style}\"");
+    let geometry = format!(
+        "<mxGeometry x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" as=\"geometry\"/>",
+        num(left),
+        num(top),
+        num(rect.width),
+        num(rect.height)
+    );
+    write_cell(out, &node.data, &cell, &geometry);
+
+    for &child in nested.into_iter().flatten() {
+        write_node(out, graph, rects, children, child, Some(rect), depth + 1);
+    }
+}
+
+/// Write cell, wrapped into `UserObject` when it carries a link or tooltip.
+fn write_cell(out: &mut String, data: &Data, cell: &str, geometry: &str) {
+    let id = attr(&data.id);
+    let label = attr(data.label.as_deref().unwrap_or(""));
+    let link = entry_link(data);
+    let tooltip = [data.note.as_deref(), data.doc.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    if link.is_none() && tooltip.is_empty() {
+        let _ = writeln!(out, "        <mxCell id=\"{id}\" value=\"{label}\" {cell}>");
+        let _ = writeln!(out, "          {geometry}");
+        out.push_str("        </mxCell>\n");
+        return;
+    }
+
+    let _ = write!(out, "        <UserObject id=\"{id}\" label=\"{label}\"");
+    if let Some(link) = link {
+        let _ = write!(out, " link=\"{}\"", attr(&link));
+    }
+    if !tooltip.is_empty() {
+        let _ = write!(out, " tooltip=\"{}\"", attr(&tooltip));
+    }
+    out.push_str(">\n");
+    let _ = writeln!(out, "          <mxCell {cell}>");
+    let _ = writeln!(out, "            {geometry}");
+    out.push_str("          </mxCell>\n        </UserObject>\n");
+}
+
+/// Link to the referenced item: resolved location, or the path from reference.
+fn entry_link(data: &Data) -> Option<String> {
+    if let Some(location) = &data.location {
+        return Some(location_url(location));
+    }
+    if let Some(url) = data_str(data, "url") {
+        return Some(url.into());
+    }
+    let r#ref = data.r#ref.as_deref()?;
+    let path = r#ref
+        .strip_prefix("lsp://")
+        .or_else(|| r#ref.strip_prefix("file://"))?;
+    let path = path.split(['#', '?']).next().unwrap_or(path);
+    (!path.is_empty()).then(|| path.into())
+}
+
+/// draw.io style for the editor node shape.
+fn drawio_shape(shape: &str) -> &str {
+    match shape {
+        "ellipse" => "ellipse",
+        "rectangle" => "rounded=0",
+        "round-rectangle" | "bottom-round-rectangle" => "rounded=1",
+        "triangle" | "round-triangle" => "triangle;direction=north",
+        "vee" => "triangle;direction=south",
+        "diamond" | "round-diamond" => "rhombus",
+        "rhomboid" | "right-rhomboid" => "shape=parallelogram;perimeter=parallelogramPerimeter",
+        "hexagon" | "round-hexagon" | "concave-hexagon" => {
+            "shape=hexagon;perimeter=hexagonPerimeter2"
+        }
+        "barrel" => "shape=cylinder3;boundedLbl=1",
+        "tag" | "round-tag" => "shape=offPageConnector;direction=east",
+        "star" => "shape=mxgraph.basic.star",
+        _ => "rounded=0",
+    }
+}
+
+/// Escape attribute value, keeping line breaks.
+fn attr(text: &str) -> String {
+    escape_xml(text).replace('\n', "&#xa;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_containers() {
+        use crate::graph::Entry;
+
+        let mut graph = Graph::default();
+        let mut group = Data::new("group");
+        group.label = Some("Core".into());
+        graph.nodes.push(Entry::node(group));
+
+        let mut parser = Data::new("parser");
+        parser.parent = Some("group".into());
+        parser.label = Some("Parser".into());
+        parser.r#ref = Some("lsp://src/parser.rs#Parser".into());
+        parser.location = Some("src/parser.rs:12".into());
+        parser.valid = Some(true);
+        parser.note = Some("Turns \"tokens\"\ninto tree".into());
+        parser.doc = Some("Parser docs".into());
+        let mut parser = Entry::node(parser);
+        parser.position.x = 100.0;
+        parser.position.y = 50.0;
+        graph.nodes.push(parser);
+
+        let mut lexer = Data::new("lexer");
+        lexer.r#ref = Some("lsp://src/lexer.rs#Lexer".into());
+        lexer.data.insert("shape".into(), "diamond".into());
+        graph.nodes.push(Entry::node(lexer));
+
+        let mut edge = Data::link("parser->lexer", "parser", "lexer");
+        edge.label = Some("uses".into());
+        graph.edges.push(Entry::edge(edge));
+
+        let output = write(&graph);
+        assert!(output.contains(r#"<mxCell id="group" value="Core" vertex="1" parent="islands-layer" style="rounded=0;html=0;whiteSpace=wrap;container=1;"#));
+        assert!(output.contains(
+        r#"<UserObject id="parser" label="Parser" link="src/parser.rs#L12" tooltip="Turns &quot;tokens&quot;&#xa;into tree&#xa;&#xa;Parser docs">"#
+    ));
+        // Child is placed relative to the container.
+        assert!(
+            output.contains(r#"<mxGeometry x="10" y="10" width="30" height="30" as="geometry"/>"#)
+        );
+        assert!(output.contains(r#"vertex="1" parent="group""#));
+        assert!(output.contains(r#"<UserObject id="lexer" label="" link="src/lexer.rs">"#));
+        assert!(output.contains("style=\"rhombus;html=0;verticalAlign=top;labelPosition=center;verticalLabelPosition=bottom;strokeColor=#ffec99;"));
+        assert!(output.contains(
+        r#"<mxCell id="parser-&gt;lexer" value="uses" edge="1" parent="islands-layer" source="parser" target="lexer""#
+    ));
+        // Cells appear after their parents.
+        assert!(output.find("id=\"group\"") < output.find("id=\"parser\""));
+    }
+}
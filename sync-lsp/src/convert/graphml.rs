@@ -0,0 +1,579 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use anyhow::{Context as _, bail};
+use indexmap::IndexMap;
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use serde_json::{Number, Value};
+
+use crate::convert::{children, escape_xml, roots};
+use crate::graph::{Data, Entry, Graph};
+use unwrap_or::unwrap_some_or;
+
+/// Keys for the fixed `Data` fields and node position: id, domain and type.
+const BUILTIN_KEYS: &[(&str, &str, &str)] = &[
+    ("label", "all", "string"),
+    ("note", "all", "string"),
+    ("ref", "all", "string"),
+    ("valid", "all", "boolean"),
+    ("location", "all", "string"),
+    ("doc", "all", "string"),
+    ("x", "node", "double"),
+    ("y", "node", "double"),
+];
+
+/// Description which marks string keys holding JSON-encoded values.
+const JSON_DESC: &str = "json";
+
+/// GraphML type of custom data field.
+#[derive(Clone, Copy, PartialEq)]
+enum KeyType {
+    Boolean,
+    Long,
+    Double,
+    String,
+    /// Arrays, objects or mixed types, stored as JSON strings.
+    Json,
+}
+
+impl KeyType {
+    fn of(value: &Value) -> KeyType {
+        match value {
+            Value::Bool(_) => KeyType::Boolean,
+            Value::Number(number) if number.is_f64() => KeyType::Double,
+            Value::Number(_) => KeyType::Long,
+            Value::String(_) => KeyType::String,
+            Value::Null | Value::Array(_) | Value::Object(_) => KeyType::Json,
+        }
+    }
+
+    fn merge(self, other: KeyType) -> KeyType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (KeyType::Long, KeyType::Double) | (KeyType::Double, KeyType::Long) => KeyType::Double,
+            _ => KeyType::Json,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            KeyType::Boolean => "boolean",
+            KeyType::Long => "long",
+            KeyType::Double => "double",
+            KeyType::String | KeyType::Json => "string",
+        }
+    }
+}
+
+/// Convert graph to GraphML. Custom data fields get typed keys, compound
+/// nodes contain nested graphs.
+pub(crate) fn write(graph: &Graph) -> String {
+    let children = children(graph);
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\"",
+        " xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\"",
+        " xsi:schemaLocation=\"http://graphml.graphdrawing.org/xmlns",
+        " http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd\">\n",
+    ));
+
+    for (id, domain, ty) in BUILTIN_KEYS {
+        let _ = writeln!(
+            out,
+            "  <key id=\"{id}\" for=\"{domain}\" attr.name=\"{id}\" attr.type=\"{ty}\"/>"
+        );
+    }
+    let keys = custom_keys(graph);
+    for ((domain, name), ty) in &keys {
+        let id = escape_xml(&format!("{domain}.{name}"));
+        let name = escape_xml(name);
+        let ty_name = ty.name();
+        let _ = write!(
+            out,
+            "  <key id=\"{id}\" for=\"{domain}\" attr.name=\"{name}\" attr.type=\"{ty_name}\""
+        );
+        if *ty == KeyType::Json {
+            let _ = writeln!(out, "><desc>{JSON_DESC}</desc></key>");
+        } else {
+            out.push_str("/>\n");
+        }
+    }
+
+    out.push_str("  <graph id=\"islands\" edgedefault=\"directed\">\n");
+    write_custom(&mut out, &keys, "graph", &graph.data, 2);
+    for index in roots(graph) {
+        write_node(&mut out, graph, &keys, &children, index, 2);
+    }
+    for edge in graph.edges.iter().filter(|edge| !edge.removed) {
+        let (source, target) = match (&edge.data.source, &edge.data.target) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+        let _ = writeln!(
+            out,
+            "    <edge id=\"{}\" source=\"{}\" target=\"{}\">",
+            escape_xml(&edge.data.id),
+            escape_xml(source),
+            escape_xml(target)
+        );
+        write_data(&mut out, &keys, "edge", &edge.data, 3);
+        out.push_str("    </edge>\n");
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+fn write_node(
+    out: &mut String,
+    graph: &Graph,
+    keys: &IndexMap<(&str, &str), KeyType>,
+    children: &HashMap<&str, Vec<usize>>,
+    index: usize,
+    depth: usize,
+) {
+    let node = &graph.nodes[index];
+    let indent = "  ".repeat(depth);
+    let _ = writeln!(out, "{indent}<node id=\"{}\">", escape_xml(&node.data.id));
+    write_data(out, keys, "node", &node.data, depth + 1);
+    let _ = writeln!(out, "{indent}  <data key=\"x\">{}</data>", node.position.x);
+    let _ = writeln!(out, "{indent}  <data key=\"y\">{}</data>", node.position.y);
+
+    if let Some(nested) = children.get(node.data.id.as_str())
+        && depth <= graph.nodes.len()
+    {
+        let _ = writeln!(
+            out,
+            "{indent}  <graph id=\"{}::\" edgedefault=\"directed\">",
+            escape_xml(&node.data.id)
+        );
+        for &child in nested {
+            write_node(out, graph, keys, children, child, depth + 2);
+        }
+        let _ = writeln!(out, "{indent}  </graph>");
+    }
+    let _ = writeln!(out, "{indent}</node>");
+}
+
+fn write_data(
+    out: &mut String,
+    keys: &IndexMap<(&str, &str), KeyType>,
+    domain: &str,
+    data: &Data,
+    depth: usize,
+) {
+    let indent = "  ".repeat(depth);
+    let fields = [
+        ("label", &data.label),
+        ("note", &data.note),
+        ("ref", &data.r#ref),
+        ("location", &data.location),
+        ("doc", &data.doc),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            let _ = writeln!(
+                out,
+                "{indent}<data key=\"{key}\">{}</data>",
+                escape_xml(value)
+            );
+        }
+    }
+    if let Some(valid) = data.valid {
+        let _ = writeln!(out, "{indent}<data key=\"valid\">{valid}</data>");
+    }
+    write_custom(out, keys, domain, &data.data, depth);
+}
+
+fn write_custom(
+    out: &mut String,
+    keys: &IndexMap<(&str, &str), KeyType>,
+    domain: &str,
+    data: &IndexMap<String, Value>,
+    depth: usize,
+) {
+    let indent = "  ".repeat(depth);
+    for (name, value) in data {
+        let text = match (keys.get(&(domain, name.as_str())), value) {
+            (Some(KeyType::Json), value) => value.to_string(),
+            (_, Value::String(text)) => text.clone(),
+            (_, value) => value.to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "{indent}<data key=\"{}\">{}</data>",
+            escape_xml(&format!("{domain}.{name}")),
+            escape_xml(&text)
+        );
+    }
+}
+
+/// Types of custom data fields by domain and name, in order of appearance.
+fn custom_keys(graph: &Graph) -> IndexMap<(&str, &str), KeyType> {
+    fn add<'a>(
+        keys: &mut IndexMap<(&'a str, &'a str), KeyType>,
+        domain: &'a str,
+        data: &'a IndexMap<String, Value>,
+    ) {
+        for (name, value) in data {
+            let ty = KeyType::of(value);
+            keys.entry((domain, name.as_str()))
+                .and_modify(|current| *current = current.merge(ty))
+                .or_insert(ty);
+        }
+    }
+
+    let mut keys = IndexMap::new();
+    add(&mut keys, "graph", &graph.data);
+    for node in graph.nodes.iter().filter(|node| !node.removed) {
+        add(&mut keys, "node", &node.data.data);
+    }
+    for edge in graph.edges.iter().filter(|edge| !edge.removed) {
+        add(&mut keys, "edge", &edge.data.data);
+    }
+    keys
+}
+
+/// Key declared in GraphML document.
+#[derive(Default)]
+struct Key {
+    name: String,
+    domain: String,
+    ty: String,
+    json: bool,
+    default: Option<String>,
+}
+
+/// Element which receives `data` values.
+#[derive(Clone, Copy)]
+enum Owner {
+    Graph,
+    Node(usize),
+    Edge(usize),
+}
+
+/// Element which text content is being collected.
+enum Capture {
+    Default,
+    Desc,
+    Data(String, Option<Owner>),
+}
+
+/// Parse GraphML document. Keys are matched by `attr.name`: the ones which
+/// correspond to `Data` fields and position are applied directly, the rest
+/// go to custom data fields.
+pub(crate) fn read(source: &str) -> anyhow::Result<Graph> {
+    let mut reader = GraphReader::default();
+    let mut xml = Reader::from_str(source);
+
+    loop {
+        let position = xml.buffer_position();
+        let result = match xml.read_event().context("Invalid XML")? {
+            Event::Start(element) => reader.start(&element),
+            Event::Empty(element) => reader
+                .start(&element)
+                .and_then(|()| reader.end(element.local_name().as_ref())),
+            Event::End(element) => reader.end(element.local_name().as_ref()),
+            Event::Text(text) => {
+                if reader.capture.is_some() {
+                    reader.text.push_str(&text.unescape()?);
+                }
+                Ok(())
+            }
+            Event::CData(text) => {
+                if reader.capture.is_some() {
+                    reader.text.push_str(std::str::from_utf8(&text)?);
+                }
+                Ok(())
+            }
+            Event::Eof => break,
+            _ => Ok(()),
+        };
+        result.with_context(|| format!("Unable to read GraphML at offset {position}"))?;
+    }
+
+    Ok(reader.graph)
+}
+
+#[derive(Default)]
+struct GraphReader {
+    graph: Graph,
+    keys: HashMap<String, Key>,
+    /// Key which is being declared.
+    key: Option<(String, Key)>,
+    owners: Vec<Owner>,
+    /// Compound node which contains the current graph.
+    parents: Vec<Option<String>>,
+    capture: Option<Capture>,
+    text: String,
+    edge_ids: HashSet<String>,
+}
+
+impl GraphReader {
+    fn start(&mut self, element: &BytesStart) -> anyhow::Result<()> {
+        match element.local_name().as_ref() {
+            b"key" => {
+                let id = attribute(element, "id")?.context("Key without id")?;
+                let key = Key {
+                    name: attribute(element, "attr.name")?.unwrap_or_default(),
+                    domain: attribute(element, "for")?.unwrap_or_else(|| "all".into()),
+                    ty: attribute(element, "attr.type")?.unwrap_or_else(|| "string".into()),
+                    ..Key::default()
+                };
+                self.key = Some((id, key));
+            }
+            b"default" if self.key.is_some() => self.capture(Capture::Default),
+            b"desc" if self.key.is_some() => self.capture(Capture::Desc),
+            b"graph" => {
+                let parent = match self.owners.last() {
+                    Some(Owner::Node(index)) => Some(self.graph.nodes[*index].data.id.clone()),
+                    _ => None,
+                };
+                self.parents.push(parent);
+                self.owners.push(Owner::Graph);
+            }
+            b"node" => {
+                let id = attribute(element, "id")?.context("Node without id")?;
+                let mut data = Data::new(id);
+                data.parent = self.parents.last().cloned().flatten();
+                self.graph.nodes.push(Entry::node(data));
+                let index = self.graph.nodes.len() - 1;
+                self.owners.push(Owner::Node(index));
+                self.apply_defaults(Owner::Node(index))?;
+            }
+            b"edge" => {
+                let source = attribute(element, "source")?.context("Edge without source")?;
+                let target = attribute(element, "target")?.context("Edge without target")?;
+                let mut id = match attribute(element, "id")? {
+                    Some(id) => id,
+                    None => format!("{source}->{target}"),
+                };
+                let mut suffix = 1;
+                while !self.edge_ids.insert(id.clone()) {
+                    suffix += 1;
+                    id = format!("{source}->{target}-{suffix}");
+                }
+                self.graph
+                    .edges
+                    .push(Entry::edge(Data::link(id, &source, &target)));
+                let index = self.graph.edges.len() - 1;
+                self.owners.push(Owner::Edge(index));
+                self.apply_defaults(Owner::Edge(index))?;
+            }
+            b"data" => {
+                let key = attribute(element, "key")?.context("Data without key")?;
+                self.capture(Capture::Data(key, self.owners.last().copied()));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, name: &[u8]) -> anyhow::Result<()> {
+        match name {
+            b"key" => {
+                if let Some((id, key)) = self.key.take() {
+                    self.keys.insert(id, key);
+                }
+            }
+            b"default" | b"desc" | b"data" => {
+                let text = std::mem::take(&mut self.text);
+                match (self.capture.take(), &mut self.key) {
+                    (Some(Capture::Default), Some((_, key))) => key.default = Some(text),
+                    (Some(Capture::Desc), Some((_, key))) => key.json = text.trim() == JSON_DESC,
+                    (Some(Capture::Data(key, Some(owner))), _) => self.assign(owner, &key, text)?,
+                    _ => {}
+                }
+            }
+            b"graph" => {
+                self.parents.pop();
+                self.owners.pop();
+            }
+            b"node" | b"edge" => {
+                self.owners.pop();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn capture(&mut self, capture: Capture) {
+        self.text.clear();
+        self.capture = Some(capture);
+    }
+
+    fn apply_defaults(&mut self, owner: Owner) -> anyhow::Result<()> {
+        let domain = match owner {
+            Owner::Node(_) => "node",
+            Owner::Edge(_) => "edge",
+            Owner::Graph => "graph",
+        };
+        let defaults: Vec<(String, String)> = self
+            .keys
+            .iter()
+            .filter(|(_, key)| key.domain == domain || key.domain == "all")
+            .filter_map(|(id, key)| Some((id.clone(), key.default.clone()?)))
+            .collect();
+        for (id, text) in defaults {
+            self.assign(owner, &id, text)?;
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, owner: Owner, key: &str, text: String) -> anyhow::Result<()> {
+        let key = unwrap_some_or!(self.keys.get(key), return Ok(()));
+        // Keys without name hold tool-specific markup, e.g. yEd graphics.
+        if key.name.is_empty() {
+            return Ok(());
+        }
+
+        let entry = match owner {
+            Owner::Graph => {
+                let value = typed_value(key, text)?;
+                self.graph.data.insert(key.name.clone(), value);
+                return Ok(());
+            }
+            Owner::Node(index) => &mut self.graph.nodes[index],
+            Owner::Edge(index) => &mut self.graph.edges[index],
+        };
+        let data = &mut entry.data;
+        match key.name.as_str() {
+            "label" => data.label = Some(text),
+            "note" => data.note = Some(text),
+            "ref" => data.r#ref = Some(text),
+            "location" => data.location = Some(text),
+            "doc" => data.doc = Some(text),
+            "valid" => data.valid = Some(parse_bool(&text)?),
+            "x" if matches!(owner, Owner::Node(_)) => {
+                entry.position.x = text.trim().parse().context("Invalid x coordinate")?
+            }
+            "y" if matches!(owner, Owner::Node(_)) => {
+                entry.position.y = text.trim().parse().context("Invalid y coordinate")?
+            }
+            name => {
+                let value = typed_value(key, text)?;
+                data.data.insert(name.into(), value);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn attribute(element: &BytesStart, name: &str) -> anyhow::Result<Option<String>> {
+    match element.try_get_attribute(name)? {
+        Some(attribute) => Ok(Some(attribute.unescape_value()?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+fn parse_bool(text: &str) -> anyhow::Result<bool> {
+    match text.trim() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        other => bail!("Invalid boolean value '{other}'"),
+    }
+}
+
+fn typed_value(key: &Key, text: String) -> anyhow::Result<Value> {
+    if key.json {
+        return serde_json::from_str(&text)
+            .with_context(|| format!("Invalid JSON in '{}'", key.name));
+    }
+    Ok(match key.ty.as_str() {
+        "boolean" => Value::Bool(parse_bool(&text)?),
+        // Parsed as JSON number to keep integers in double fields intact.
+        "int" | "long" | "float" | "double" => Value::Number(
+            serde_json::from_str::<Number>(text.trim())
+                .with_context(|| format!("Invalid number in '{}'", key.name))?,
+        ),
+        _ => Value::String(text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut graph = Graph::default();
+        graph.data.insert("zoom".into(), 1.5.into());
+
+        let mut group = Data::new("group");
+        group.label = Some("Group & co".into());
+        group.data.insert("shape".into(), "round-rectangle".into());
+        graph.nodes.push(Entry::node(group));
+
+        let mut node = Data::new("a");
+        node.parent = Some("group".into());
+        node.label = Some("A <main>".into());
+        node.note = Some("Multi\nline".into());
+        node.r#ref = Some("lsp://src/a.rs#A".into());
+        node.valid = Some(false);
+        node.location = Some("src/a.rs:3".into());
+        node.doc = Some("Docs".into());
+        node.data.insert("size".into(), 40.into());
+        node.data
+            .insert("tags".into(), serde_json::json!(["core", 1]));
+        let mut node = Entry::node(node);
+        node.position.x = 10.5;
+        node.position.y = -20.0;
+        graph.nodes.push(node);
+
+        let mut node = Data::new("b");
+        node.data.insert("size".into(), 25.5.into());
+        graph.nodes.push(Entry::node(node));
+
+        let mut edge = Data::link("a->b", "a", "b");
+        edge.label = Some("uses".into());
+        edge.data.insert("weight".into(), 2.into());
+        graph.edges.push(Entry::edge(edge));
+
+        let output = write(&graph);
+        assert!(
+            output.contains(
+                r#"<key id="node.size" for="node" attr.name="size" attr.type="double"/>"#
+            )
+        );
+        assert!(output.contains(r#"<graph id="group::" edgedefault="directed">"#));
+
+        let again = read(&output).unwrap();
+        assert_eq!(write(&again), output);
+        assert_eq!(
+            serde_json::to_value(&again).unwrap(),
+            serde_json::to_value(&graph).unwrap()
+        );
+    }
+
+    #[test]
+    fn read_foreign() {
+        let graph = read(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns" xmlns:y="http://www.yworks.com/xml/graphml">
+  <key id="d0" for="node" attr.name="color" attr.type="string"><default>yellow</default></key>
+  <key id="d1" for="edge" attr.name="weight" attr.type="double"/>
+  <key id="d2" for="node" yfiles.type="nodegraphics"/>
+  <graph id="G" edgedefault="directed">
+    <node id="n0"><data key="d0">green</data><data key="d2"><y:ShapeNode><y:NodeLabel>n0</y:NodeLabel></y:ShapeNode></data></node>
+    <node id="n1">
+      <graph id="n1:">
+        <node id="n1::n0"/>
+      </graph>
+    </node>
+    <edge source="n0" target="n1::n0"><data key="d1">1.0</data></edge>
+    <edge source="n0" target="n1::n0"/>
+  </graph>
+</graphml>"#,
+    )
+    .unwrap();
+
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.nodes[0].data.data["color"], "green");
+        assert_eq!(graph.nodes[0].data.data.len(), 1);
+        assert_eq!(graph.nodes[1].data.data["color"], "yellow");
+        assert_eq!(graph.nodes[2].data.parent.as_deref(), Some("n1"));
+        assert_eq!(graph.edges[0].data.id, "n0->n1::n0");
+        assert_eq!(graph.edges[0].data.data["weight"], 1.0);
+        assert_eq!(graph.edges[1].data.id, "n0->n1::n0-2");
+    }
+}
@@ -0,0 +1,706 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use anyhow::{Context as _, bail};
+
+use crate::convert::{children, data_str, plain_ids, roots};
+use crate::graph::{Data, Entry, Graph};
+
+/// Editor node shapes and matching Mermaid node brackets. Nodes without the
+/// shape are written as plain `[...]` boxes.
+pub(crate) const SHAPES: &[(&str, &str, &str)] = &[
+    ("round-rectangle", "(", ")"),
+    ("ellipse", "((", "))"),
+    ("diamond", "{", "}"),
+    ("hexagon", "{{", "}}"),
+    ("rhomboid", "[/", "/]"),
+    ("right-rhomboid", "[\\", "\\]"),
+    ("barrel", "[(", ")]"),
+    ("tag", ">", "]"),
+];
+
+/// Words which can't be used as Mermaid node ids.
+const RESERVED: &[&str] = &[
+    "end",
+    "graph",
+    "flowchart",
+    "subgraph",
+    "style",
+    "class",
+    "click",
+    "direction",
+    "classdef",
+    "linkstyle",
+];
+
+/// Comment directive restoring the editor id of nodes which were renamed.
+const ID_DIRECTIVE: &str = "%% id ";
+
+/// Comment directive carrying the node reference.
+const REF_DIRECTIVE: &str = "%% ref ";
+
+/// Produce Mermaid flowchart from the graph. Compound nodes become subgraphs.
+/// Node ids are kept when Mermaid accepts them, renamed ids and references
+/// are written in `%%` comment directives.
+pub(crate) fn write(graph: &Graph) -> String {
+    let children = children(graph);
+    let ids = node_ids(graph);
+    let mut out = String::from("flowchart TD\n");
+
+    for index in roots(graph) {
+        write_node(&mut out, graph, &children, &ids, index, 1);
+    }
+
+    for edge in graph.edges.iter().filter(|edge| !edge.removed) {
+        let source = edge.data.source.as_deref().and_then(|id| ids.get(id));
+        let target = edge.data.target.as_deref().and_then(|id| ids.get(id));
+        let (source, target) = match (source, target) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+        match &edge.data.label {
+            Some(label) => {
+                let _ = writeln!(out, "    {source} -->|{}| {target}", quote(label));
+            }
+            None => {
+                let _ = writeln!(out, "    {source} --> {target}");
+            }
+        }
+    }
+
+    out
+}
+
+fn write_node(
+    out: &mut String,
+    graph: &Graph,
+    children: &HashMap<&str, Vec<usize>>,
+    ids: &HashMap<&str, String>,
+    index: usize,
+    depth: usize,
+) {
+    let node = &graph.nodes[index];
+    let indent = "    ".repeat(depth);
+    let id = &ids[node.data.id.as_str()];
+    let label = node.data.label.as_deref().unwrap_or(&node.data.id);
+
+    match children.get(node.data.id.as_str()) {
+        Some(nested) if depth <= graph.nodes.len() => {
+            let _ = writeln!(out, "{indent}subgraph {id}[{}]", quote(label));
+            write_directives(out, &node.data, id, &indent);
+            for &child in nested {
+                write_node(out, graph, children, ids, child, depth + 1);
+            }
+            let _ = writeln!(out, "{indent}end");
+        }
+        _ => {
+            let shape = data_str(&node.data, "shape");
+            let (open, close) = SHAPES
+                .iter()
+                .find(|(name, _, _)| Some(*name) == shape)
+                .map_or(("[", "]"), |(_, open, close)| (*open, *close));
+            let _ = writeln!(out, "{indent}{id}{open}{}{close}", quote(label));
+            write_directives(out, &node.data, id, &indent);
+        }
+    }
+}
+
+fn write_directives(out: &mut String, data: &Data, id: &str, indent: &str) {
+    if data.id != id {
+        let _ = writeln!(out, "{indent}{ID_DIRECTIVE}{id} {}", data.id);
+    }
+    if let Some(r#ref) = &data.r#ref {
+        let _ = writeln!(out, "{indent}{REF_DIRECTIVE}{id} {ref}");
+    }
+}
+
+/// Mermaid ids of the nodes. Ids which the reader accepts as they are kept,
+/// others are replaced with plain ones.
+fn node_ids(graph: &Graph) -> HashMap<&str, String> {
+    let mut ids = HashMap::new();
+    let mut taken = HashSet::new();
+    for node in &graph.nodes {
+        let id = node.data.id.as_str();
+        if is_node_id(id) && taken.insert(id.to_string()) {
+            ids.insert(id, id.to_string());
+        }
+    }
+
+    let plain = plain_ids(graph, RESERVED);
+    for node in &graph.nodes {
+        let id = node.data.id.as_str();
+        if ids.contains_key(id) {
+            continue;
+        }
+        let base = &plain[id];
+        let mut candidate = base.clone();
+        let mut suffix = 1;
+        while !taken.insert(candidate.clone()) {
+            suffix += 1;
+            candidate = format!("{base}_{suffix}");
+        }
+        ids.insert(id, candidate);
+    }
+    ids
+}
+
+/// Words of letters, digits and underscores joined with single dashes, which
+/// is what [`Reader::node`] takes for an id.
+fn is_node_id(id: &str) -> bool {
+    !RESERVED.contains(&id.to_lowercase().as_str())
+        && id.split('-').all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+/// Quote the label text, quotes and newlines are replaced with Mermaid entities.
+fn quote(text: &str) -> String {
+    let text = text
+        .replace('"', "#quot;")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>");
+    format!("\"{text}\"")
+}
+
+/// Link notations with the text placed inside: `A -- text --> B`.
+const TEXT_LINKS: &[(&str, &[&str])] = &[
+    ("--", &["-->", "---", "--x", "--o"]),
+    ("==", &["==>", "===", "==x", "==o"]),
+    ("-.", &[".->", ".-"]),
+];
+
+/// Parse Mermaid flowchart into graph. Subgraphs become compound nodes, node
+/// brackets are mapped to the `shape` data field, and the `%%` directives
+/// written by [`write`] restore node ids and references.
+pub(crate) fn read(source: &str) -> anyhow::Result<Graph> {
+    let mut reader = Reader::default();
+    let mut header = false;
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with("%%") {
+            reader.comment(line);
+            continue;
+        }
+        for statement in split_statements(line) {
+            let statement = statement.trim();
+            if statement.is_empty() || statement.starts_with("%%") {
+                continue;
+            }
+            if !header {
+                let keyword = statement.split_whitespace().next().unwrap_or("");
+                if keyword != "flowchart" && keyword != "graph" {
+                    bail!("Expected flowchart header, found '{statement}'");
+                }
+                header = true;
+                continue;
+            }
+            reader
+                .statement(statement)
+                .with_context(|| format!("Unable to parse line {}", number + 1))?;
+        }
+    }
+    if !header {
+        bail!("Flowchart header is missing");
+    }
+
+    Ok(reader.finish())
+}
+
+/// Node data restored from the comment directives.
+enum Directive {
+    Id,
+    Ref,
+}
+
+#[derive(Default)]
+struct Reader {
+    graph: Graph,
+    /// Mermaid id to node index.
+    nodes: HashMap<String, usize>,
+    /// Stack of open subgraphs.
+    subgraphs: Vec<String>,
+    edge_ids: HashSet<String>,
+    /// Directives with the Mermaid id of their node.
+    directives: Vec<(Directive, String, String)>,
+}
+
+impl Reader {
+    /// Collect `%% id` and `%% ref` directives, other comments are skipped.
+    fn comment(&mut self, line: &str) {
+        let (directive, rest) = if let Some(rest) = line.strip_prefix(ID_DIRECTIVE) {
+            (Directive::Id, rest)
+        } else if let Some(rest) = line.strip_prefix(REF_DIRECTIVE) {
+            (Directive::Ref, rest)
+        } else {
+            return;
+        };
+        if let Some((id, value)) = rest.trim().split_once(' ') {
+            self.directives
+                .push((directive, id.into(), value.trim().into()));
+        }
+    }
+
+    /// Apply the directives to the nodes they name, directives of unknown
+    /// nodes are ignored.
+    fn finish(mut self) -> Graph {
+        let mut renamed: HashMap<String, String> = HashMap::new();
+        for (directive, id, value) in self.directives {
+            let Some(&index) = self.nodes.get(&id) else {
+                continue;
+            };
+            let data = &mut self.graph.nodes[index].data;
+            match directive {
+                Directive::Id => {
+                    data.id = value.clone();
+                    renamed.insert(id, value);
+                }
+                Directive::Ref => data.r#ref = Some(value),
+            }
+        }
+
+        let rename = |id: &mut Option<String>| {
+            if let Some(new) = id.as_ref().and_then(|id| renamed.get(id)) {
+                *id = Some(new.clone());
+            }
+        };
+        for node in &mut self.graph.nodes {
+            rename(&mut node.data.parent);
+        }
+        for edge in &mut self.graph.edges {
+            rename(&mut edge.data.source);
+            rename(&mut edge.data.target);
+        }
+        self.graph
+    }
+
+    fn statement(&mut self, statement: &str) -> anyhow::Result<()> {
+        let keyword = statement.split_whitespace().next().unwrap_or("");
+        match keyword {
+            "end" => {
+                self.subgraphs.pop().context("Unexpected 'end'")?;
+                return Ok(());
+            }
+            "subgraph" => return self.subgraph(statement["subgraph".len()..].trim()),
+            "direction" | "classDef" | "class" | "style" | "linkStyle" | "click" => {
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let mut rest = statement;
+        let mut previous: Option<Vec<String>> = None;
+        let mut link_label: Option<String> = None;
+        loop {
+            let mut group = Vec::new();
+            loop {
+                let (id, rest_after) = self.node(rest)?;
+                group.push(id);
+                rest = rest_after.trim_start();
+                match rest.strip_prefix('&') {
+                    Some(after) => rest = after.trim_start(),
+                    None => break,
+                }
+            }
+
+            if let Some(sources) = previous.take() {
+                for source in &sources {
+                    for target in &group {
+                        self.edge(source, target, link_label.clone());
+                    }
+                }
+            }
+
+            if rest.is_empty() {
+                return Ok(());
+            }
+            let (label, after) =
+                parse_link(rest).with_context(|| format!("Unexpected input: '{rest}'"))?;
+            link_label = label;
+            rest = after.trim_start();
+            previous = Some(group);
+        }
+    }
+
+    /// `subgraph id[title]`, `subgraph id ["title"]` or `subgraph title`.
+    fn subgraph(&mut self, header: &str) -> anyhow::Result<()> {
+        let (id, label) = match header.find('[') {
+            Some(start) if header.ends_with(']') => (
+                header[..start].trim().to_string(),
+                Some(unquote(&header[start + 1..header.len() - 1])),
+            ),
+            _ if header.starts_with('"') => (String::new(), Some(unquote(header))),
+            _ => (header.to_string(), None),
+        };
+        let id = if id.is_empty() {
+            format!("subgraph-{}", self.graph.nodes.len() + 1)
+        } else {
+            id
+        };
+
+        let index = self.declare(&id);
+        if label.is_some() {
+            self.graph.nodes[index].data.label = label;
+        }
+        self.subgraphs.push(id);
+        Ok(())
+    }
+
+    /// Parse node reference with optional shape and label.
+    /// Returns node id and the remaining input.
+    fn node<'a>(&mut self, input: &'a str) -> anyhow::Result<(String, &'a str)> {
+        let end = input
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(input.len());
+        // Allow dashes inside ids as long as they don't start a link.
+        let mut end = end;
+        while input[end..].starts_with('-')
+            && input[end + 1..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        {
+            end += 1;
+            end += input[end..]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(input.len() - end);
+        }
+        let id = &input[..end];
+        if id.is_empty() {
+            bail!("Expected node id, found '{input}'");
+        }
+        let rest = &input[end..];
+
+        let index = self.declare(id);
+        let (shape, label, rest) = match parse_shape(rest)? {
+            Some(parsed) => parsed,
+            None => return Ok((id.into(), rest)),
+        };
+
+        let data = &mut self.graph.nodes[index].data;
+        data.label = Some(label);
+        match shape {
+            Some(shape) => {
+                data.data.insert("shape".into(), shape.into());
+            }
+            None => {
+                data.data.shift_remove("shape");
+            }
+        }
+        Ok((id.into(), rest))
+    }
+
+    /// Register node if it wasn't seen before, placing it in the current subgraph.
+    fn declare(&mut self, id: &str) -> usize {
+        if let Some(&index) = self.nodes.get(id) {
+            return index;
+        }
+        let mut data = Data::new(id);
+        data.parent = self.subgraphs.last().cloned();
+        self.graph.nodes.push(Entry::node(data));
+        self.nodes.insert(id.into(), self.graph.nodes.len() - 1);
+        self.graph.nodes.len() - 1
+    }
+
+    fn edge(&mut self, source: &str, target: &str, label: Option<String>) {
+        let mut id = format!("{source}->{target}");
+        let mut suffix = 1;
+        while !self.edge_ids.insert(id.clone()) {
+            suffix += 1;
+            id = format!("{source}->{target}-{suffix}");
+        }
+        let mut data = Data::link(id, source, target);
+        data.label = label;
+        self.graph.edges.push(Entry::edge(data));
+    }
+}
+
+/// Split line by `;` separators which are not inside quotes or brackets.
+fn split_statements(line: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut quoted = false;
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' | '(' | '{' if !quoted => depth += 1,
+            ']' | ')' | '}' if !quoted => depth -= 1,
+            ';' if !quoted && depth <= 0 => {
+                statements.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&line[start..]);
+    statements
+}
+
+/// Parse node brackets right after the id: returns editor shape, label and
+/// the remaining input.
+fn parse_shape(input: &str) -> anyhow::Result<Option<(Option<&'static str>, String, &str)>> {
+    // Longer brackets go first, so that `((` is not taken for `(`.
+    const EXTRA: &[(Option<&str>, &str, &str)] = &[
+        (Some("ellipse"), "(((", ")))"),
+        (Some("round-rectangle"), "([", "])"),
+        (None, "[[", "]]"),
+        (Some("rhomboid"), "[/", "\\]"),
+        (Some("right-rhomboid"), "[\\", "/]"),
+    ];
+    let mut brackets: Vec<(Option<&str>, &str, &str)> = SHAPES
+        .iter()
+        .map(|(shape, open, close)| (Some(*shape), *open, *close))
+        .chain(EXTRA.iter().copied())
+        .chain([(None, "[", "]")])
+        .collect();
+    brackets.sort_by_key(|(_, open, _)| std::cmp::Reverse(open.len()));
+
+    for (shape, open, close) in brackets {
+        let inner = match input.strip_prefix(open) {
+            Some(inner) => inner,
+            None => continue,
+        };
+        let end = if let Some(quoted) = inner.strip_prefix('"') {
+            let quote_end = quoted.find('"').context("Unterminated label")? + 2;
+            if !inner[quote_end..].starts_with(close) {
+                continue;
+            }
+            quote_end
+        } else {
+            match inner.find(close) {
+                Some(end) => end,
+                None => continue,
+            }
+        };
+        let label = unquote(&inner[..end]);
+        return Ok(Some((shape, label, &inner[end + close.len()..])));
+    }
+    Ok(None)
+}
+
+/// Parse link between nodes. Returns link text and the remaining input.
+fn parse_link(input: &str) -> Option<(Option<String>, &str)> {
+    for (open, closes) in TEXT_LINKS {
+        let text = match input.strip_prefix(open) {
+            Some(text) if text.starts_with(' ') => text,
+            _ => continue,
+        };
+        if let Some((end, close)) = closes
+            .iter()
+            .filter_map(|close| Some((text.find(close)?, close)))
+            .min_by_key(|(end, _)| *end)
+        {
+            let label = unquote(text[..end].trim());
+            return Some((Some(label), &text[end + close.len()..]));
+        }
+    }
+
+    let input = input.strip_prefix('<').unwrap_or(input);
+    let end = input
+        .find(|c: char| !matches!(c, '-' | '=' | '.'))
+        .unwrap_or(input.len());
+    if end < 2 {
+        return None;
+    }
+    let mut rest = &input[end..];
+    // `x` and `o` arrowheads are ambiguous with node ids starting with them.
+    if let Some(after) = rest.strip_prefix(['>', 'x', 'o'])
+        && (rest.starts_with('>') || !after.starts_with(|c: char| c.is_alphanumeric()))
+    {
+        rest = after;
+    }
+
+    let rest = rest.trim_start();
+    if let Some(text) = rest.strip_prefix('|') {
+        let end = text.find('|')?;
+        return Some((Some(unquote(&text[..end])), &text[end + 1..]));
+    }
+    Some((None, rest))
+}
+
+/// Remove quotes and decode entities and line breaks of Mermaid text.
+fn unquote(text: &str) -> String {
+    let text = text.trim();
+    let text = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .unwrap_or(text);
+    let text = text
+        .strip_prefix('`')
+        .and_then(|t| t.strip_suffix('`'))
+        .unwrap_or(text);
+    text.replace("#quot;", "\"")
+        .replace("#35;", "#")
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n")
+        .replace("<br>", "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_flowchart() {
+        let mut graph = Graph::default();
+        let mut data = Data::new("group");
+        data.label = Some("Group".into());
+        graph.nodes.push(Entry::node(data));
+
+        let mut data = Data::new("main-fn");
+        data.parent = Some("group".into());
+        data.label = Some("main \"fn\"".into());
+        data.data.insert("shape".into(), "diamond".into());
+        graph.nodes.push(Entry::node(data));
+
+        graph.nodes.push(Entry::node(Data::new("end")));
+        let mut data = Data::new("a.b");
+        data.r#ref = Some("lsp://src/a.rs#b".into());
+        graph.nodes.push(Entry::node(data));
+        let mut data = Data::link("e1", "end", "group");
+        data.label = Some("uses".into());
+        graph.edges.push(Entry::edge(data));
+        graph
+            .edges
+            .push(Entry::edge(Data::link("e2", "main-fn", "end")));
+
+        assert_eq!(
+            write(&graph),
+            r#"flowchart TD
+    subgraph group["Group"]
+        main-fn{"main #quot;fn#quot;"}
+    end
+    n_end["end"]
+    %% id n_end end
+    a_b["a.b"]
+    %% id a_b a.b
+    %% ref a_b lsp://src/a.rs#b
+    n_end -->|"uses"| group
+    main-fn --> n_end
+"#
+        );
+    }
+
+    #[test]
+    fn read_flowchart() {
+        let graph = read(
+            r#"flowchart LR
+    %% comment
+    subgraph core["Core modules"]
+        direction TB
+        parser("Parser<br>module") --> lexer{{Lexer}}
+    end
+    subgraph Storage
+        db[(Database)]
+    end
+    cli>CLI] -- parses --> parser & db
+    lexer -.->|"tokens"| db; db --- cli
+    click cli "https://example.com"
+"#,
+        )
+        .unwrap();
+
+        let node = |id: &str| graph.nodes.iter().find(|n| n.data.id == id).unwrap();
+        assert_eq!(node("core").data.label.as_deref(), Some("Core modules"));
+        assert_eq!(node("parser").data.parent.as_deref(), Some("core"));
+        assert_eq!(node("parser").data.label.as_deref(), Some("Parser\nmodule"));
+        assert_eq!(
+            data_str(&node("parser").data, "shape"),
+            Some("round-rectangle")
+        );
+        assert_eq!(data_str(&node("lexer").data, "shape"), Some("hexagon"));
+        assert_eq!(node("db").data.parent.as_deref(), Some("Storage"));
+        assert_eq!(data_str(&node("db").data, "shape"), Some("barrel"));
+        assert_eq!(data_str(&node("cli").data, "shape"), Some("tag"));
+        assert_eq!(node("cli").data.parent, None);
+
+        let edges: Vec<(&str, &str, Option<&str>)> = graph
+            .edges
+            .iter()
+            .map(|e| {
+                (
+                    e.data.source.as_deref().unwrap(),
+                    e.data.target.as_deref().unwrap(),
+                    e.data.label.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                ("parser", "lexer", None),
+                ("cli", "parser", Some("parses")),
+                ("cli", "db", Some("parses")),
+                ("lexer", "db", Some("tokens")),
+                ("db", "cli", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = r#"flowchart TD
+    subgraph group["Group"]
+        a("A")
+        subgraph inner["Inner"]
+            b{"B #quot;quoted#quot;"}
+        end
+    end
+    c["C<br>multiline"]
+    d(("D"))
+    c -->|"uses"| group
+    a --> d
+"#;
+        let graph = read(source).unwrap();
+        assert_eq!(write(&graph), source);
+
+        let mut graph = Graph::default();
+        let mut data = Data::new("core-mod");
+        data.label = Some("Core".into());
+        data.r#ref = Some("lsp://src/core.rs?kind=module".into());
+        graph.nodes.push(Entry::node(data));
+        for (id, parent, r#ref) in [
+            ("main-fn", Some("core-mod"), Some("lsp://src/main.rs#main")),
+            ("a.b", Some("core-mod"), None),
+            ("end", None, Some("file://README.md")),
+            ("a_b", None, None),
+        ] {
+            let mut data = Data::new(id);
+            data.label = Some(id.into());
+            data.parent = parent.map(Into::into);
+            data.r#ref = r#ref.map(Into::into);
+            graph.nodes.push(Entry::node(data));
+        }
+        graph
+            .edges
+            .push(Entry::edge(Data::link("e1", "main-fn", "a.b")));
+        graph
+            .edges
+            .push(Entry::edge(Data::link("e2", "end", "a_b")));
+
+        let read = read(&write(&graph)).unwrap();
+        let nodes = |graph: &Graph| -> Vec<(String, Option<String>, Option<String>)> {
+            let mut nodes: Vec<_> = graph
+                .nodes
+                .iter()
+                .map(|n| {
+                    (
+                        n.data.id.clone(),
+                        n.data.parent.clone(),
+                        n.data.r#ref.clone(),
+                    )
+                })
+                .collect();
+            nodes.sort();
+            nodes
+        };
+        assert_eq!(nodes(&read), nodes(&graph));
+        let edges: Vec<_> = read
+            .edges
+            .iter()
+            .map(|e| (e.data.source.as_deref(), e.data.target.as_deref()))
+            .collect();
+        assert_eq!(
+            edges,
+            [(Some("main-fn"), Some("a.b")), (Some("end"), Some("a_b"))]
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Conversion between the graph and third-party diagram formats.
+
+pub(crate) mod canvas;
+pub(crate) mod dot;
+pub(crate) mod drawio;
+pub(crate) mod graphml;
+pub(crate) mod mermaid;
+pub(crate) mod plantuml;
+pub(crate) mod svg;
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::graph::{Data, Graph};
+
+/// Node width and height used by the editor when `size` is not set.
+pub(crate) const DEFAULT_SIZE: f64 = 30.0;
+
+/// Space between compound node border and its children.
+pub(crate) const PARENT_PADDING: f64 = 10.0;
+
+/// Axis-aligned rectangle defined by its center and dimensions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn left(&self) -> f64 {
+        self.x - self.width / 2.0
+    }
+
+    pub fn top(&self) -> f64 {
+        self.y - self.height / 2.0
+    }
+
+    pub fn right(&self) -> f64 {
+        self.x + self.width / 2.0
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height / 2.0
+    }
+
+    /// Smallest rectangle which contains both rectangles.
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect::from_corners(
+            self.left().min(other.left()),
+            self.top().min(other.top()),
+            self.right().max(other.right()),
+            self.bottom().max(other.bottom()),
+        )
+    }
+
+    pub fn from_corners(left: f64, top: f64, right: f64, bottom: f64) -> Rect {
+        Rect {
+            x: (left + right) / 2.0,
+            y: (top + bottom) / 2.0,
+            width: right - left,
+            height: bottom - top,
+        }
+    }
+
+    pub fn grow(&self, padding: f64) -> Rect {
+        Rect {
+            width: self.width + padding * 2.0,
+            height: self.height + padding * 2.0,
+            ..*self
+        }
+    }
+}
+
+/// Custom string field from node data (e.g. `shape`).
+pub(crate) fn data_str<'a>(data: &'a Data, key: &str) -> Option<&'a str> {
+    data.data.get(key).and_then(Value::as_str)
+}
+
+/// Node width and height from `size` data field.
+pub(crate) fn node_size(data: &Data) -> f64 {
+    data.data
+        .get("size")
+        .and_then(Value::as_f64)
+        .filter(|size| *size > 0.0)
+        .unwrap_or(DEFAULT_SIZE)
+}
+
+/// Compute node rectangles the way the editor does: leaf nodes are sized by
+/// the `size` field, compound nodes wrap their children with padding.
+/// Removed nodes are skipped.
+pub(crate) fn node_rects(graph: &Graph) -> HashMap<&str, Rect> {
+    let mut rects = HashMap::new();
+    let children = children(graph);
+
+    fn visit<'a>(
+        graph: &'a Graph,
+        index: usize,
+        children: &HashMap<&str, Vec<usize>>,
+        rects: &mut HashMap<&'a str, Rect>,
+        depth: usize,
+    ) -> Option<Rect> {
+        let node = &graph.nodes[index];
+        if let Some(rect) = rects.get(node.data.id.as_str()) {
+            return Some(*rect);
+        }
+        if node.removed || depth > graph.nodes.len() {
+            return None;
+        }
+
+        let nested = children.get(node.data.id.as_str());
+        let rect = nested
+            .into_iter()
+            .flatten()
+            .filter_map(|&child| visit(graph, child, children, rects, depth + 1))
+            .reduce(|a, b| a.union(&b));
+        let rect = match rect {
+            Some(rect) => rect.grow(PARENT_PADDING),
+            None => {
+                let size = node_size(&node.data);
+                Rect {
+                    x: node.position.x,
+                    y: node.position.y,
+                    width: size,
+                    height: size,
+                }
+            }
+        };
+        rects.insert(node.data.id.as_str(), rect);
+        Some(rect)
+    }
+
+    for index in 0..graph.nodes.len() {
+        visit(graph, index, &children, &mut rects, 0);
+    }
+    rects
+}
+
+/// Map of compound node id to indices of its children.
+pub(crate) fn children(graph: &Graph) -> HashMap<&str, Vec<usize>> {
+    let mut children: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, node) in graph.nodes.iter().enumerate() {
+        if node.removed {
+            continue;
+        }
+        if let Some(parent) = &node.data.parent {
+            children.entry(parent.as_str()).or_default().push(index);
+        }
+    }
+    children
+}
+
+/// Indices of the top-level nodes. Nodes whose parent is missing or removed
+/// are top-level as well, so they are not lost.
+pub(crate) fn roots(graph: &Graph) -> Vec<usize> {
+    let present: HashSet<&str> = graph
+        .nodes
+        .iter()
+        .filter(|node| !node.removed)
+        .map(|node| node.data.id.as_str())
+        .collect();
+    graph
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| {
+            !node.removed
+                && node
+                    .data
+                    .parent
+                    .as_deref()
+                    .is_none_or(|parent| !present.contains(parent))
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Node ids restricted to `[A-Za-z0-9_]`, as required by text diagram formats.
+/// Ids are made unique, and the reserved words get `n_` prefix.
+pub(crate) fn plain_ids<'a>(graph: &'a Graph, reserved: &[&str]) -> HashMap<&'a str, String> {
+    let mut taken = HashSet::new();
+    let mut ids = HashMap::new();
+    for node in &graph.nodes {
+        let mut base: String = node
+            .data
+            .id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        if base.is_empty() || reserved.contains(&base.to_lowercase().as_str()) {
+            base = format!("n_{base}");
+        }
+        let mut id = base.clone();
+        let mut suffix = 1;
+        while !taken.insert(id.clone()) {
+            suffix += 1;
+            id = format!("{base}_{suffix}");
+        }
+        ids.insert(node.data.id.as_str(), id);
+    }
+    ids
+}
+
+/// Nesting depth of the node within compound parents.
+pub(crate) fn depth(graph: &Graph, id: &str) -> usize {
+    let parents: HashMap<&str, &str> = graph
+        .nodes
+        .iter()
+        .filter_map(|node| Some((node.data.id.as_str(), node.data.parent.as_deref()?)))
+        .collect();
+    let mut depth = 0;
+    let mut current = id;
+    while let Some(parent) = parents.get(current) {
+        depth += 1;
+        current = parent;
+        if depth > parents.len() {
+            break;
+        }
+    }
+    depth
+}
+
+/// Convert `path:line` location into relative link with line anchor.
+pub(crate) fn location_url(location: &str) -> String {
+    match location.rsplit_once(':') {
+        Some((path, line)) if line.parse::<u32>().is_ok() => format!("{path}#L{line}"),
+        _ => location.into(),
+    }
+}
+
+/// Format coordinate with up to two decimals, dropping the fraction when it's zero.
+pub(crate) fn num(value: f64) -> String {
+    let rounded = (value * 100.0).round() / 100.0;
+    if rounded == rounded.trunc() {
+        format!("{}", rounded as i64)
+    } else {
+        format!("{rounded}")
+    }
+}
+
+/// Escape text for XML attribute values and content.
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compound_rects() {
+        use crate::graph::Entry;
+
+        let mut graph = Graph::default();
+        graph.nodes.push(Entry::node(Data::new("group")));
+        for (id, x) in [("a", 0.0), ("b", 100.0)] {
+            let mut data = Data::new(id);
+            data.parent = Some("group".into());
+            let mut node = Entry::node(data);
+            node.position.x = x;
+            graph.nodes.push(node);
+        }
+        graph.nodes[2].data.data.insert("size".into(), 50.into());
+
+        let rects = node_rects(&graph);
+        assert_eq!(rects["a"].width, DEFAULT_SIZE);
+        assert_eq!(rects["b"].width, 50.0);
+        assert_eq!(
+            rects["group"],
+            Rect::from_corners(-15.0, -25.0, 125.0, 25.0).grow(PARENT_PADDING)
+        );
+    }
+
+    #[test]
+    fn dangling_parents() {
+        use crate::graph::Entry;
+
+        let mut graph = Graph::default();
+        graph.nodes.push(Entry::node(Data::new("group")));
+        graph.nodes[0].removed = true;
+        for (id, parent) in [("a", "group"), ("b", "missing"), ("c", "b")] {
+            let mut data = Data::new(id);
+            data.parent = Some(parent.into());
+            graph.nodes.push(Entry::node(data));
+        }
+
+        assert_eq!(roots(&graph), [1, 2]);
+    }
+}
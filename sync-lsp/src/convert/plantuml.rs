@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use crate::convert::{children, location_url, plain_ids, roots};
+use crate::graph::{Data, Graph};
+use crate::noderef::{KindMarker, NodeRef, RefType};
+
+/// Words which can't be used as PlantUML aliases.
+const RESERVED: &[&str] = &[
+    "package",
+    "component",
+    "class",
+    "interface",
+    "struct",
+    "enum",
+    "end",
+    "as",
+    "left",
+    "right",
+    "up",
+    "down",
+    "skinparam",
+    "together",
+];
+
+/// Produce PlantUML component diagram. Compound nodes become packages or
+/// components, and the `?kind=` declared in `lsp://` references selects
+/// element types.
+pub(crate) fn write(graph: &Graph) -> String {
+    let children = children(graph);
+    let ids = plain_ids(graph, RESERVED);
+    // Class-like elements are only allowed next to components in mixed mode.
+    let mut out = String::from("@startuml\nallowmixing\n");
+
+    for index in roots(graph) {
+        write_node(&mut out, graph, &children, &ids, index, 0);
+    }
+
+    for edge in graph.edges.iter().filter(|edge| !edge.removed) {
+        let source = edge.data.source.as_deref().and_then(|id| ids.get(id));
+        let target = edge.data.target.as_deref().and_then(|id| ids.get(id));
+        let (source, target) = match (source, target) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+        match &edge.data.label {
+            Some(label) => {
+                let _ = writeln!(out, "{source} --> {target} : {}", escape(label));
+            }
+            None => {
+                let _ = writeln!(out, "{source} --> {target}");
+            }
+        }
+    }
+
+    out.push_str("@enduml\n");
+    out
+}
+
+fn write_node(
+    out: &mut String,
+    graph: &Graph,
+    children: &HashMap<&str, Vec<usize>>,
+    ids: &HashMap<&str, String>,
+    index: usize,
+    depth: usize,
+) {
+    let node = &graph.nodes[index];
+    let indent = "  ".repeat(depth);
+    let id = &ids[node.data.id.as_str()];
+    let label = escape(node.data.label.as_deref().unwrap_or(&node.data.id));
+    let kind = declared_kind(&node.data);
+    let link = match &node.data.location {
+        Some(location) => format!(" [[{}]]", location_url(location)),
+        None => String::new(),
+    };
+
+    match children.get(node.data.id.as_str()) {
+        Some(nested) if depth <= graph.nodes.len() => {
+            let block = match kind {
+                None
+                | Some(
+                    KindMarker::File
+                    | KindMarker::Module
+                    | KindMarker::Namespace
+                    | KindMarker::Package,
+                ) => "package",
+                Some(_) => "component",
+            };
+            let _ = writeln!(out, "{indent}{block} \"{label}\" as {id}{link} {{");
+            for &child in nested {
+                write_node(out, graph, children, ids, child, depth + 1);
+            }
+            let _ = writeln!(out, "{indent}}}");
+        }
+        _ => {
+            let element = match kind {
+                Some(KindMarker::Struct) => "struct",
+                Some(KindMarker::Interface) => "interface",
+                Some(KindMarker::Class) => "class",
+                Some(KindMarker::Enum) => "enum",
+                _ => "component",
+            };
+            let _ = writeln!(out, "{indent}{element} \"{label}\" as {id}{link}");
+        }
+    }
+}
+
+/// Symbol kind declared with `?kind=` in the `lsp://` reference. The kind
+/// resolved by the server isn't stored in the graph, so undeclared kinds
+/// give plain components.
+fn declared_kind(data: &Data) -> Option<KindMarker> {
+    let node_ref = NodeRef::parse_ref(data.r#ref.as_deref()?).ok()?;
+    match node_ref.schema {
+        RefType::Lsp => node_ref.params.kind,
+        _ => None,
+    }
+}
+
+/// PlantUML strings can't contain quotes, and line breaks are written as `\n`.
+fn escape(text: &str) -> String {
+    text.replace('"', "'")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_components() {
+        use crate::graph::Entry;
+
+        let mut graph = Graph::default();
+        let mut core = Data::new("core");
+        core.label = Some("Core".into());
+        graph.nodes.push(Entry::node(core));
+
+        let mut parser = Data::new("parser");
+        parser.r#ref = Some("lsp://src/parser.rs?kind=struct#Parser".into());
+        parser.label = Some("Parser".into());
+        graph.nodes.push(Entry::node(parser));
+
+        let nodes = [
+            ("token", "lsp://src/token.rs?kind=enum#Token"),
+            ("reader", "lsp://src/io.rs?kind=interface#Read"),
+            ("main", "lsp://src/main.rs?kind=function#main"),
+            ("readme", "file://README.md"),
+        ];
+        for (id, r#ref) in nodes {
+            let mut data = Data::new(id);
+            data.r#ref = Some(r#ref.into());
+            data.parent = Some("parser".into());
+            graph.nodes.push(Entry::node(data));
+        }
+        graph.nodes[4].data.parent = Some("core".into());
+        graph.nodes[4].data.location = Some("src/main.rs:3".into());
+        graph.nodes[5].data.parent = None;
+        graph.nodes[1].data.parent = Some("core".into());
+
+        let mut edge = Data::link("main->parser", "main", "parser");
+        edge.label = Some("creates \"it\"".into());
+        graph.edges.push(Entry::edge(edge));
+        graph
+            .edges
+            .push(Entry::edge(Data::link("readme->core", "readme", "core")));
+
+        assert_eq!(
+            write(&graph),
+            r#"@startuml
+allowmixing
+package "Core" as core {
+  component "Parser" as parser {
+    enum "token" as token
+    interface "reader" as reader
+  }
+  component "main" as main [[src/main.rs#L3]]
+}
+component "readme" as readme
+main --> parser : creates 'it'
+readme --> core
+@enduml
+"#
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+
+use crate::convert::{Rect, children, data_str, depth, escape_xml, node_rects, num};
+use crate::graph::{Data, Graph};
+
+/// Space around the drawing.
+const MARGIN: f64 = 20.0;
+
+/// Label font size, matches the editor style.
+const FONT_SIZE: f64 = 16.0;
+
+/// Approximate glyph width of the monospace label font.
+const CHAR_WIDTH: f64 = FONT_SIZE * 0.6;
+
+const BORDER_COLOR: &str = "#333";
+const NODE_COLOR: &str = "#fff";
+const INVALID_COLOR: &str = "#900";
+const UNCHECKED_COLOR: &str = "#ffec99";
+
+/// Render the graph into standalone SVG document using the editor light style.
+pub(crate) fn render(graph: &Graph) -> String {
+    let rects = node_rects(graph);
+    let children = children(graph);
+
+    let mut bounds: Option<Rect> = None;
+    for node in &graph.nodes {
+        let rect = match rects.get(node.data.id.as_str()) {
+            Some(rect) => *rect,
+            None => continue,
+        };
+        let mut extended = rect.grow(6.0);
+        if let Some(label) = &node.data.label {
+            let label = label_rect(label, &rect, children.contains_key(node.data.id.as_str()));
+            extended = extended.union(&label);
+        }
+        bounds = Some(bounds.map_or(extended, |b| b.union(&extended)));
+    }
+    let bounds = bounds.unwrap_or(Rect::from_corners(0.0, 0.0, 0.0, 0.0));
+    let bounds = bounds.grow(MARGIN);
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+        num(bounds.left()),
+        num(bounds.top()),
+        num(bounds.width),
+        num(bounds.height),
+        num(bounds.width),
+        num(bounds.height),
+    );
+    let _ = writeln!(
+        out,
+        r#"<defs><marker id="tee" viewBox="0 -5 2 10" refX="1" refY="0" markerWidth="2" markerHeight="10" markerUnits="userSpaceOnUse" orient="auto"><rect x="0" y="-5" width="2" height="10" fill="{BORDER_COLOR}"/></marker></defs>"#,
+    );
+    let _ = writeln!(
+        out,
+        r#"<g font-family="monospace" font-size="{}" text-anchor="middle">"#,
+        num(FONT_SIZE)
+    );
+
+    // Compound nodes go first (outer ones below inner ones), then edges and nodes.
+    let mut parents: Vec<&Data> = graph
+        .nodes
+        .iter()
+        .filter(|node| !node.removed && children.contains_key(node.data.id.as_str()))
+        .map(|node| &node.data)
+        .collect();
+    parents.sort_by_key(|data| depth(graph, &data.id));
+    for data in parents {
+        let rect = rects[data.id.as_str()];
+        render_outline(&mut out, data, &rect, "round-rectangle");
+        let _ = writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" rx="10" fill="{NODE_COLOR}" fill-opacity="0.3" stroke="{BORDER_COLOR}" stroke-width="2"/>"#,
+            num(rect.left()),
+            num(rect.top()),
+            num(rect.width),
+            num(rect.height),
+        );
+        if let Some(label) = &data.label {
+            render_label(&mut out, label, &label_rect(label, &rect, true));
+        }
+    }
+
+    for edge in graph.edges.iter().filter(|edge| !edge.removed) {
+        let source = edge.data.source.as_deref().and_then(|id| rects.get(id));
+        let target = edge.data.target.as_deref().and_then(|id| rects.get(id));
+        let (source, target) = match (source, target) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+        let ((x1, y1), (x2, y2)) = (clip(source, target), clip(target, source));
+        let _ = writeln!(
+            out,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{BORDER_COLOR}" stroke-width="3" marker-end="url(#tee)"/>"#,
+            num(x1),
+            num(y1),
+            num(x2),
+            num(y2),
+        );
+        if let Some(label) = &edge.data.label {
+            let center = Rect {
+                x: (x1 + x2) / 2.0,
+                y: (y1 + y2) / 2.0,
+                width: 0.0,
+                height: 0.0,
+            };
+            render_label(&mut out, label, &label_rect(label, &center, false));
+        }
+    }
+
+    for node in &graph.nodes {
+        if children.contains_key(node.data.id.as_str()) {
+            continue;
+        }
+        let rect = match rects.get(node.data.id.as_str()) {
+            Some(rect) => *rect,
+            None => continue,
+        };
+        let shape = data_str(&node.data, "shape").unwrap_or("ellipse");
+        render_outline(&mut out, &node.data, &rect, shape);
+        render_shape(&mut out, &rect, shape, NODE_COLOR, BORDER_COLOR, 2.0);
+        if let Some(label) = &node.data.label {
+            render_label(&mut out, label, &label_rect(label, &rect, false));
+        }
+    }
+
+    out.push_str("</g>\n</svg>\n");
+    out
+}
+
+/// Draw reference validity outline: red for failed check, yellow for
+/// reference which wasn't checked yet.
+fn render_outline(out: &mut String, data: &Data, rect: &Rect, shape: &str) {
+    if data.r#ref.is_none() {
+        return;
+    }
+    let (color, width, opacity) = match data.valid {
+        Some(false) => (INVALID_COLOR, 4.0, 1.0),
+        Some(true) => (BORDER_COLOR, 2.0, 0.5),
+        None => (UNCHECKED_COLOR, 2.0, 1.0),
+    };
+    let outline = rect.grow(2.0 + 2.0 + width / 2.0);
+    let _ = write!(out, r#"<g stroke-opacity="{opacity}">"#);
+    render_shape(out, &outline, shape, "none", color, width);
+    out.push_str("</g>\n");
+}
+
+fn render_shape(out: &mut String, rect: &Rect, shape: &str, fill: &str, stroke: &str, width: f64) {
+    let style = format!(
+        r#"fill="{fill}" stroke="{stroke}" stroke-width="{}""#,
+        num(width)
+    );
+    let (base, round) = match shape.strip_prefix("round-") {
+        Some(base) => (base, true),
+        None => (shape, false),
+    };
+    match base {
+        "ellipse" => {
+            let _ = writeln!(
+                out,
+                r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" {style}/>"#,
+                num(rect.x),
+                num(rect.y),
+                num(rect.width / 2.0),
+                num(rect.height / 2.0),
+            );
+        }
+        _ => match polygon(base) {
+            Some(points) => {
+                let points: Vec<String> = points
+                    .iter()
+                    .map(|(x, y)| {
+                        format!(
+                            "{},{}",
+                            num(rect.x + x * rect.width / 2.0),
+                            num(rect.y + y * rect.height / 2.0)
+                        )
+                    })
+                    .collect();
+                let join = if round {
+                    r#" stroke-linejoin="round""#
+                } else {
+                    ""
+                };
+                let _ = writeln!(
+                    out,
+                    r#"<polygon points="{}" {style}{join}/>"#,
+                    points.join(" ")
+                );
+            }
+            None => {
+                let radius = if round {
+                    rect.width.min(rect.height) / 4.0
+                } else {
+                    0.0
+                };
+                let _ = writeln!(
+                    out,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}" {style}/>"#,
+                    num(rect.left()),
+                    num(rect.top()),
+                    num(rect.width),
+                    num(rect.height),
+                    num(radius),
+                );
+            }
+        },
+    }
+}
+
+/// Polygon points for the editor node shapes, in `-1..1` coordinates.
+/// Rectangles return `None` and are drawn as `rect`.
+fn polygon(shape: &str) -> Option<Vec<(f64, f64)>> {
+    let regular = |sides: usize| {
+        (0..sides)
+            .map(|i| {
+                let angle = -PI / 2.0 + 2.0 * PI * i as f64 / sides as f64;
+                (angle.cos(), angle.sin())
+            })
+            .collect()
+    };
+    Some(match shape {
+        "triangle" => vec![(0.0, -1.0), (1.0, 1.0), (-1.0, 1.0)],
+        "diamond" => vec![(0.0, -1.0), (1.0, 0.0), (0.0, 1.0), (-1.0, 0.0)],
+        "pentagon" => regular(5),
+        "hexagon" => vec![
+            (-0.5, -1.0),
+            (0.5, -1.0),
+            (1.0, 0.0),
+            (0.5, 1.0),
+            (-0.5, 1.0),
+            (-1.0, 0.0),
+        ],
+        "concave-hexagon" => vec![
+            (-1.0, -1.0),
+            (1.0, -1.0),
+            (0.8, 0.0),
+            (1.0, 1.0),
+            (-1.0, 1.0),
+            (-0.8, 0.0),
+        ],
+        "heptagon" => regular(7),
+        "octagon" => regular(8),
+        "star" => (0..10)
+            .map(|i| {
+                let angle = -PI / 2.0 + PI * i as f64 / 5.0;
+                let radius = if i % 2 == 0 { 1.0 } else { 0.4 };
+                (radius * angle.cos(), radius * angle.sin())
+            })
+            .collect(),
+        "rhomboid" => vec![(-0.6, -1.0), (1.0, -1.0), (0.6, 1.0), (-1.0, 1.0)],
+        "right-rhomboid" => vec![(-1.0, -1.0), (0.6, -1.0), (1.0, 1.0), (-0.6, 1.0)],
+        "vee" => vec![(-1.0, -1.0), (0.0, -0.33), (1.0, -1.0), (0.0, 1.0)],
+        "tag" => vec![
+            (-1.0, -1.0),
+            (0.5, -1.0),
+            (1.0, 0.0),
+            (0.5, 1.0),
+            (-1.0, 1.0),
+        ],
+        "cut-rectangle" => vec![
+            (-0.7, -1.0),
+            (0.7, -1.0),
+            (1.0, -0.7),
+            (1.0, 0.7),
+            (0.7, 1.0),
+            (-0.7, 1.0),
+            (-1.0, 0.7),
+            (-1.0, -0.7),
+        ],
+        _ => return None,
+    })
+}
+
+/// Label position: centered over the node, or at the top for compound nodes.
+fn label_rect(label: &str, rect: &Rect, compound: bool) -> Rect {
+    let lines: Vec<&str> = label.lines().collect();
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as f64 * CHAR_WIDTH;
+    let height = lines.len().max(1) as f64 * FONT_SIZE * 1.2;
+    let y = if compound {
+        rect.top() - height / 2.0 - 2.0
+    } else {
+        rect.y - 2.0
+    };
+    Rect {
+        x: rect.x,
+        y,
+        width,
+        height,
+    }
+}
+
+fn render_label(out: &mut String, label: &str, rect: &Rect) {
+    let lines: Vec<&str> = label.lines().collect();
+    let line_height = FONT_SIZE * 1.2;
+    let first = rect.y - line_height * (lines.len() as f64 - 1.0) / 2.0;
+    let _ = write!(
+        out,
+        r#"<text x="{}" y="{}" dominant-baseline="central" stroke="{NODE_COLOR}" stroke-width="4" paint-order="stroke">"#,
+        num(rect.x),
+        num(first),
+    );
+    for (i, line) in lines.iter().enumerate() {
+        let _ = write!(
+            out,
+            r#"<tspan x="{}" dy="{}">{}</tspan>"#,
+            num(rect.x),
+            if i == 0 { 0.0 } else { line_height },
+            escape_xml(line)
+        );
+    }
+    out.push_str("</text>\n");
+}
+
+/// Point where the line from `from` center towards `to` center leaves `from` bounds.
+fn clip(from: &Rect, to: &Rect) -> (f64, f64) {
+    let (dx, dy) = (to.x - from.x, to.y - from.y);
+    if dx == 0.0 && dy == 0.0 {
+        return (from.x, from.y);
+    }
+    let scale_x = if dx != 0.0 {
+        from.width / 2.0 / dx.abs()
+    } else {
+        f64::INFINITY
+    };
+    let scale_y = if dy != 0.0 {
+        from.height / 2.0 / dy.abs()
+    } else {
+        f64::INFINITY
+    };
+    let scale = scale_x.min(scale_y).min(1.0);
+    (from.x + dx * scale, from.y + dy * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::{Data, Entry, Graph};
+
+    #[test]
+    fn render_graph() {
+        let mut graph = Graph::default();
+        let mut data = Data::new("a");
+        data.label = Some("A & B".into());
+        data.r#ref = Some("lsp://src/main.rs#main".into());
+        data.valid = Some(false);
+        data.data.insert("shape".into(), "diamond".into());
+        graph.nodes.push(Entry::node(data));
+
+        let mut data = Data::new("b");
+        data.r#ref = Some("file://README.md".into());
+        let mut node = Entry::node(data);
+        node.position.x = 100.0;
+        graph.nodes.push(node);
+        graph.edges.push(Entry::edge(Data::link("e", "a", "b")));
+
+        let svg = super::render(&graph);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("A &amp; B"));
+        assert!(svg.contains("<polygon points=\"0,-15 15,0 0,15 -15,0\""));
+        assert!(svg.contains(r##"stroke="#900""##));
+        assert!(svg.contains(r##"stroke="#ffec99""##));
+        assert!(svg.contains(r#"<line x1="15" y1="0" x2="85" y2="0""#));
+    }
+}
@@ -0,0 +1,196 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use log::info;
+
+use crate::settings::{Readiness, Server};
+
+/// Language server candidates for a project kind.
+struct Candidate {
+    /// Files which mark the project kind.
+    markers: &'static [&'static str],
+    /// Server commands with arguments, in order of preference.
+    commands: &'static [(&'static str, &'static [&'static str])],
+    extensions: &'static [&'static str],
+    /// Readiness strategy, if the server needs a specific one.
+    readiness: Option<Readiness>,
+}
+
+const CANDIDATES: &[Candidate] = &[
+    Candidate {
+        markers: &["Cargo.toml"],
+        commands: &[("rust-analyzer", &[])],
+        extensions: &["rs"],
+        readiness: Some(Readiness::Tokens),
+    },
+    Candidate {
+        markers: &["tsconfig.json", "package.json"],
+        commands: &[("typescript-language-server", &["--stdio"])],
+        extensions: &["ts", "tsx", "js", "jsx", "mts", "cts", "mjs", "cjs"],
+        readiness: None,
+    },
+    Candidate {
+        markers: &["go.mod"],
+        commands: &[("gopls", &[])],
+        extensions: &["go"],
+        readiness: None,
+    },
+    Candidate {
+        markers: &["pyproject.toml"],
+        commands: &[("pyright-langserver", &["--stdio"]), ("pylsp", &[])],
+        extensions: &["py", "pyi"],
+        readiness: None,
+    },
+    Candidate {
+        markers: &["compile_commands.json"],
+        commands: &[("clangd", &[])],
+        extensions: &["c", "h", "cc", "cpp", "cxx", "hh", "hpp", "hxx"],
+        readiness: None,
+    },
+];
+
+/// Servers detected in the workspace.
+pub(crate) struct Detected {
+    /// Servers found in `PATH`, the first one is the default.
+    pub servers: Vec<Server>,
+    /// Project markers found without any of their servers in `PATH`.
+    pub missing: Vec<Missing>,
+}
+
+/// Project marker whose servers are not in `PATH`.
+pub(crate) struct Missing {
+    pub marker: &'static str,
+    pub commands: Vec<&'static str>,
+}
+
+/// Marker with the servers looked for.
+impl fmt::Display for Missing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.marker, self.commands.join(" or "))
+    }
+}
+
+/// Detect language servers from project markers in the workspace root.
+pub(crate) fn detect(root: &Path, path: Option<&OsStr>) -> Detected {
+    let mut servers = Vec::new();
+    let mut missing = Vec::new();
+    for candidate in CANDIDATES {
+        let marker = match candidate
+            .markers
+            .iter()
+            .find(|marker| root.join(marker).is_file())
+        {
+            Some(marker) => marker,
+            None => continue,
+        };
+
+        let found = candidate
+            .commands
+            .iter()
+            .find_map(|&(command, args)| Some((command, args, find_executable(command, path)?)));
+        let (command, args, executable) = match found {
+            Some(found) => found,
+            None => {
+                missing.push(Missing {
+                    marker,
+                    commands: candidate
+                        .commands
+                        .iter()
+                        .map(|(command, _)| *command)
+                        .collect(),
+                });
+                continue;
+            }
+        };
+        info!(
+            "Found {marker}, using {command} from {}",
+            executable.display()
+        );
+
+        let mut server = Server::new(command);
+        server.args = args.iter().map(|arg| arg.to_string()).collect();
+        server.extensions = candidate.extensions.iter().map(|e| e.to_string()).collect();
+        server.readiness = candidate.readiness;
+        servers.push(server);
+    }
+    Detected { servers, missing }
+}
+
+/// Readiness strategy the known server needs, if any. The command may be
+/// given with a path.
+pub(crate) fn readiness(command: &str) -> Option<Readiness> {
+    let name = Path::new(command).file_stem()?.to_str()?;
+    CANDIDATES
+        .iter()
+        .find(|candidate| candidate.commands.iter().any(|(known, _)| *known == name))?
+        .readiness
+}
+
+/// Look for the executable file in the `PATH` directories.
+fn find_executable(command: &str, path: Option<&OsStr>) -> Option<PathBuf> {
+    std::env::split_paths(path?)
+        .flat_map(|dir| {
+            let file = dir.join(command);
+            [file.with_extension(std::env::consts::EXE_EXTENSION), file]
+        })
+        .find(|file| is_executable(file))
+}
+
+#[cfg(unix)]
+fn is_executable(file: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+    file.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(file: &Path) -> bool {
+    file.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn detect_servers() {
+        use std::fs;
+
+        let dir = TempDir::new("detect");
+        let bin = dir.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        for marker in ["Cargo.toml", "go.mod", "pyproject.toml"] {
+            fs::write(dir.join(marker), "").unwrap();
+        }
+        for command in ["gopls", "pylsp", "clangd"] {
+            let file = bin.join(command);
+            fs::write(&file, "").unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt as _;
+                fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+            }
+        }
+
+        // Rust-analyzer is missing, and clangd has no marker.
+        let Detected { servers, missing } = detect(&dir, Some(bin.as_os_str()));
+        let commands: Vec<_> = servers
+            .iter()
+            .map(|server| server.command.as_str())
+            .collect();
+        assert_eq!(commands, ["gopls", "pylsp"]);
+        assert_eq!(servers[1].extensions, ["py", "pyi"]);
+        // Servers without their own strategy wait for progress to settle.
+        assert_eq!(servers[0].readiness(), Readiness::Progress);
+        let missing: Vec<_> = missing.iter().map(ToString::to_string).collect();
+        assert_eq!(missing, ["Cargo.toml (rust-analyzer)"]);
+
+        let detected = detect(&dir, None);
+        assert!(detected.servers.is_empty());
+        assert_eq!(detected.missing.len(), 3);
+        assert_eq!(readiness("/opt/bin/rust-analyzer"), Some(Readiness::Tokens));
+        assert_eq!(readiness("gopls"), None);
+    }
+}
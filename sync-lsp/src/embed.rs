@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context as _, bail};
+
+use crate::convert::mermaid;
+use crate::graph::Graph;
+
+/// Closing marker of the embedded block.
+const END_MARKER: &str = "<!-- /islands -->";
+
+/// Embedded diagram found in the document.
+pub(crate) struct Block {
+    /// Graph path as written in the marker.
+    pub graph: String,
+    /// Whether the block content differs from the generated diagram.
+    pub stale: bool,
+}
+
+/// Regenerate Mermaid blocks placed between `<!-- islands:path/to/graph.json -->`
+/// and `<!-- /islands -->` markers. Graph paths are relative to the document.
+/// Returns the updated document along with the list of found blocks.
+pub(crate) fn update(document: &str, base: &Path) -> anyhow::Result<(String, Vec<Block>)> {
+    let mut out = String::with_capacity(document.len());
+    let mut blocks = Vec::new();
+    let mut lines = document.split_inclusive('\n');
+
+    while let Some(line) = lines.next() {
+        out.push_str(line);
+        let graph = match start_marker(line) {
+            Some(graph) => graph,
+            None => continue,
+        };
+
+        let mut current = String::new();
+        let mut closed = false;
+        for line in lines.by_ref() {
+            if line.trim() == END_MARKER {
+                closed = true;
+                let generated = render(&base.join(graph))
+                    .with_context(|| format!("Unable to generate diagram for {graph}"))?;
+                out.push_str(&generated);
+                out.push_str(line);
+                blocks.push(Block {
+                    graph: graph.into(),
+                    stale: current != generated,
+                });
+                break;
+            }
+            current.push_str(line);
+        }
+        if !closed {
+            bail!("Missing '{END_MARKER}' marker after '{}'", line.trim());
+        }
+    }
+
+    Ok((out, blocks))
+}
+
+/// Process Markdown file in place, or only check it if `check` is set.
+pub(crate) fn process(path: &Path, check: bool) -> anyhow::Result<Vec<Block>> {
+    let document = fs::read_to_string(path).context("Unable to read Markdown file")?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let (updated, blocks) = update(&document, base)?;
+
+    if !check && updated != document {
+        fs::write(path, updated).context("Unable to write Markdown file")?;
+    }
+    Ok(blocks)
+}
+
+fn start_marker(line: &str) -> Option<&str> {
+    let path = line
+        .trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim()
+        .strip_prefix("islands:")?
+        .trim();
+    (!path.is_empty()).then_some(path)
+}
+
+fn render(path: &Path) -> anyhow::Result<String> {
+    let graph = Graph::from_json(path)?;
+    Ok(format!("```mermaid\n{}```\n", mermaid::write(&graph)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn update_blocks() {
+        let dir = TempDir::new("embed");
+        fs::write(
+            dir.join("graph.json"),
+            r#"{"nodes": [{"data": {"id": "a"}, "position": {"x": 0, "y": 0}, "group": "nodes",
+            "removed": false, "selected": false, "selectable": true, "locked": false,
+            "grabbable": true, "pannable": false, "classes": ""}], "edges": []}"#,
+        )
+        .unwrap();
+
+        let document = "# Title\n<!-- islands:graph.json -->\n```mermaid\nflowchart TD\n```\n<!-- /islands -->\nText\n";
+        let (updated, blocks) = update(document, &dir).unwrap();
+        assert_eq!(
+            updated,
+            "# Title\n<!-- islands:graph.json -->\n```mermaid\nflowchart TD\n    a[\"a\"]\n```\n<!-- /islands -->\nText\n"
+        );
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].stale);
+
+        let (again, blocks) = update(&updated, &dir).unwrap();
+        assert_eq!(again, updated);
+        assert!(!blocks[0].stale);
+
+        assert!(update("<!-- islands:graph.json -->\n", &dir).is_err());
+    }
+}
@@ -1,26 +1,46 @@
-use std::fs;
-use std::path::Path;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
 
-use anyhow::Context as _;
+use anyhow::{Context as _, anyhow, bail};
 use indexmap::IndexMap;
+use log::{info, warn};
 use serde::Serializer;
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::args::{OnConflict, WriteArgs};
+use crate::patch;
+use crate::settings::Settings;
+
 /// Graph data which loosely follows Cytoscape.js format, along with some
 /// additional properties.
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub(crate) struct Graph {
+    #[serde(default)]
     pub nodes: Vec<Entry>,
+    #[serde(default)]
     pub edges: Vec<Entry>,
 
-    // TODO: store settings on this level
+    #[serde(default, skip_serializing_if = "Settings::is_empty")]
+    pub settings: Settings,
+
     #[serde(flatten)]
     pub data: IndexMap<String, Value>,
+
+    /// Text the graph was parsed from, used to write minimal changes back.
+    #[serde(skip)]
+    pub source: Option<String>,
+
+    /// File the graph was loaded from, which is checked for concurrent changes.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
 }
 
-/// Node or edge entry.
+/// Node or edge entry. Missing fields get Cytoscape defaults on load.
 #[derive(Serialize, Deserialize)]
+#[serde(from = "EntryFields")]
 pub(crate) struct Entry {
     pub data: Data,
     pub position: Position,
@@ -32,10 +52,63 @@ pub(crate) struct Entry {
     pub grabbable: bool,
     pub pannable: bool,
     pub classes: String,
+
+    /// Other element properties (e.g. `scratch` or `style`), kept as is.
+    #[serde(flatten)]
+    pub extra: IndexMap<String, Value>,
+}
+
+/// Entry as written in the file, where everything but `data` is optional.
+#[derive(Deserialize)]
+struct EntryFields {
+    data: Data,
+    #[serde(default)]
+    position: Position,
+    group: Option<Group>,
+    #[serde(default)]
+    removed: bool,
+    #[serde(default)]
+    selected: bool,
+    #[serde(default = "default_true")]
+    selectable: bool,
+    #[serde(default)]
+    locked: bool,
+    #[serde(default = "default_true")]
+    grabbable: bool,
+    pannable: Option<bool>,
+    #[serde(default)]
+    classes: String,
+    #[serde(flatten)]
+    extra: IndexMap<String, Value>,
+}
+
+impl From<EntryFields> for Entry {
+    fn from(fields: EntryFields) -> Self {
+        // Cytoscape infers the group from the edge endpoints, and only edges
+        // are pannable by default.
+        let group = fields.group.unwrap_or(match fields.data.source {
+            Some(_) => Group::Edges,
+            None => Group::Nodes,
+        });
+        let pannable = fields.pannable.unwrap_or(matches!(group, Group::Edges));
+        Self {
+            data: fields.data,
+            position: fields.position,
+            group,
+            removed: fields.removed,
+            selected: fields.selected,
+            selectable: fields.selectable,
+            locked: fields.locked,
+            grabbable: fields.grabbable,
+            pannable,
+            classes: fields.classes,
+            extra: fields.extra,
+        }
+    }
 }
 
 /// Single entry, either node or edge.
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub(crate) struct Data {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
@@ -78,25 +151,542 @@ pub(crate) enum Group {
     Edges,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub(crate) struct Position {
-    #[serde(serialize_with = "serialize_zero_as_int")]
-    x: f64,
-    #[serde(serialize_with = "serialize_zero_as_int")]
-    y: f64,
+    #[serde(serialize_with = "serialize_whole_as_int")]
+    pub x: f64,
+    #[serde(serialize_with = "serialize_whole_as_int")]
+    pub y: f64,
 }
 
 impl Graph {
     pub fn from_json(path: &Path) -> anyhow::Result<Self> {
-        serde_json::from_str(&fs::read_to_string(path).context("Unable to read graph JSON file")?)
-            .context("Unable to parse graph JSON file")
+        let text = fs::read_to_string(path).context("Unable to read graph JSON file")?;
+        let mut graph = Self::parse(&text).context("Unable to parse graph JSON file")?;
+        graph.path = Some(path.into());
+        Ok(graph)
+    }
+
+    /// Parse graph JSON. Errors point at the JSON path of the bad value.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut deserializer = serde_json::Deserializer::from_str(text);
+        let mut graph: Self = serde_path_to_error::deserialize(&mut deserializer)
+            .map_err(|err| anyhow!("{}: {}", err.path(), err.inner()))?;
+        deserializer.end()?;
+        graph.source = Some(text.into());
+        Ok(graph)
+    }
+
+    /// Serialize the graph. When only reference check results changed since
+    /// the graph was parsed, these are patched into the original text, and
+    /// the rest of it is kept as is. Otherwise the graph is written in the same
+    /// layout as the editor does.
+    ///
+    /// File is replaced atomically. If it was changed since the graph was
+    /// loaded from it, the write is refused or merged depending on options.
+    pub fn save(&mut self, path: &Path, options: &WriteArgs) -> anyhow::Result<()> {
+        let current = match &self.path {
+            Some(loaded) if loaded == path => match fs::read_to_string(path) {
+                Ok(current) => Some(current),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err).context("Unable to read graph JSON file"),
+            },
+            _ => None,
+        };
+
+        let (output, merged) = match (&self.source, current) {
+            (Some(source), Some(current)) if *source != current => match options.on_conflict {
+                OnConflict::Refuse => bail!(
+                    "{} was changed since it was loaded, use `--on-conflict merge` to apply changes on top",
+                    path.display()
+                ),
+                OnConflict::Merge => {
+                    let output = patch::merge(source, &current, self).with_context(|| {
+                        format!("Unable to merge changes into {}", path.display())
+                    })?;
+                    info!("Merged with changes made to {}", path.display());
+                    (output, true)
+                }
+            },
+            _ => (self.serialize()?, false),
+        };
+
+        write_file(path, &output, options.backup).context("Unable to write graph JSON file")?;
+        // Changes made to the file are loaded, so they're kept by later saves.
+        if merged {
+            *self = Self::parse(&output).context("Unable to parse merged graph JSON")?;
+        }
+        self.source = Some(output);
+        self.path = Some(path.into());
+        Ok(())
+    }
+
+    fn serialize(&self) -> anyhow::Result<String> {
+        let patched = match &self.source {
+            Some(source) => patch::update(source, self).unwrap_or_else(|err| {
+                warn!("Unable to patch graph JSON, writing it again: {err:#}");
+                None
+            }),
+            None => None,
+        };
+        match patched {
+            Some(output) => Ok(output),
+            None => serde_json::to_string_pretty(self).context("Unable to serialize graph data"),
+        }
+    }
+}
+
+/// Write file contents through a temporary file, so that the file is either
+/// fully updated or left as is.
+fn write_file(path: &Path, contents: &str, backup: bool) -> io::Result<()> {
+    let name = path.file_name().ok_or(io::ErrorKind::InvalidInput)?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+
+    let previous = fs::metadata(path).ok();
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        if let Some(previous) = &previous {
+            file.set_permissions(previous.permissions())?;
+        }
+        file.sync_all()?;
+        if backup && previous.is_some() {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(".bak");
+            fs::copy(path, backup)?;
+        }
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+impl Entry {
+    /// Create node entry with the editor defaults.
+    pub fn node(data: Data) -> Self {
+        Self {
+            data,
+            position: Position::default(),
+            group: Group::Nodes,
+            removed: false,
+            selected: false,
+            selectable: true,
+            locked: false,
+            grabbable: true,
+            pannable: false,
+            classes: String::new(),
+            extra: IndexMap::new(),
+        }
+    }
+
+    /// Create edge entry with the editor defaults.
+    pub fn edge(data: Data) -> Self {
+        Self {
+            group: Group::Edges,
+            pannable: true,
+            ..Self::node(data)
+        }
+    }
+}
+
+impl Data {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Create edge data which links two nodes.
+    pub fn link(id: impl Into<String>, source: &str, target: &str) -> Self {
+        Self {
+            source: Some(source.into()),
+            target: Some(target.into()),
+            ..Self::new(id)
+        }
     }
 }
 
-fn serialize_zero_as_int<S: Serializer>(x: &f64, s: S) -> Result<S::Ok, S::Error> {
-    if *x == 0.0 {
-        s.serialize_i64(0)
+fn default_true() -> bool {
+    true
+}
+
+/// Whole numbers are written without fraction, the same as JavaScript does.
+fn serialize_whole_as_int<S: Serializer>(x: &f64, s: S) -> Result<S::Ok, S::Error> {
+    // Larger values aren't exact in f64, and JavaScript writes them in exponent form.
+    const MAX_EXACT: f64 = 9_007_199_254_740_991.0;
+    if x.fract() == 0.0 && x.abs() <= MAX_EXACT {
+        s.serialize_i64(*x as i64)
     } else {
         s.serialize_f64(*x)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn parse_with_defaults() {
+        let graph = Graph::parse(
+            r#"{
+            "nodes": [{"data": {"id": "a"}}, {"data": {"id": "b"}, "position": {"x": 5, "y": 1}}],
+            "edges": [{"data": {"id": "a->b", "source": "a", "target": "b"}}]
+        }"#,
+        )
+        .unwrap();
+
+        let node = &graph.nodes[0];
+        assert!(matches!(node.group, Group::Nodes));
+        assert!(node.selectable && node.grabbable && !node.pannable && !node.locked);
+        assert_eq!(graph.nodes[1].position.x, 5.0);
+        let edge = &graph.edges[0];
+        assert!(matches!(edge.group, Group::Edges));
+        assert!(edge.pannable);
+
+        let graph = Graph::parse(r#"{"nodes": [{"data": {"id": "a"}}]}"#).unwrap();
+        assert!(graph.edges.is_empty());
+    }
+
+    #[test]
+    fn parse_error_path() {
+        let err = match Graph::parse(
+            r#"{
+    "nodes": [
+        {"data": {"id": "a"}},
+        {"data": {"id": "b"}, "position": {"x": "left", "y": 0}}
+    ]
+}"#,
+        ) {
+            Ok(_) => panic!("Invalid graph is parsed"),
+            Err(err) => err,
+        };
+        let message = err.to_string();
+        assert!(
+            message.starts_with("nodes[1].position.x: invalid type"),
+            "{message}"
+        );
+        assert!(message.contains("line 4"), "{message}");
+
+        let result = Graph::parse(r#"{"nodes": [{"position": {"x": 0, "y": 0}}]}"#);
+        assert!(result.is_err_and(|err| err.to_string().contains("missing field `data`")));
+    }
+
+    /// Elements in the layout the editor exports them with Cytoscape 3.33
+    /// (`JSON.stringify(cy.json().elements, null, 2)`): generated ids, data keys
+    /// in the order they were added (`parent` last after the "Parent" action,
+    /// first for "Add child node"), fractional positions from pointer events.
+    const EDITOR_EXPORT: &str = r##"{
+  "nodes": [
+    {
+      "data": {
+        "id": "5b0e8f2a-9c41-4d7e-b3a6-1f2d8c9e0a47",
+        "label": "Core"
+      },
+      "position": {
+        "x": 326.25,
+        "y": 190
+      },
+      "group": "nodes",
+      "removed": false,
+      "selected": false,
+      "selectable": true,
+      "locked": false,
+      "grabbable": true,
+      "pannable": false,
+      "classes": ""
+    },
+    {
+      "data": {
+        "id": "c3d9a1e4-7f20-4b58-9e6d-2a8b5c0f1e93",
+        "label": "Parser",
+        "ref": "lsp://src/parser.rs?kind=struct#Parser",
+        "valid": true,
+        "location": "src/parser.rs:12",
+        "doc": "```rust\nstruct Parser\n```",
+        "shape": "round-rectangle",
+        "size": 40,
+        "parent": "5b0e8f2a-9c41-4d7e-b3a6-1f2d8c9e0a47"
+      },
+      "position": {
+        "x": 250,
+        "y": 190
+      },
+      "group": "nodes",
+      "removed": false,
+      "selected": true,
+      "selectable": true,
+      "locked": true,
+      "grabbable": true,
+      "pannable": false,
+      "classes": ""
+    },
+    {
+      "data": {
+        "parent": "5b0e8f2a-9c41-4d7e-b3a6-1f2d8c9e0a47",
+        "id": "8e47b2c0-3a19-4f6d-a5e8-9d1c7b2f0634",
+        "label": "Lexer",
+        "ref": "lsp://src/lexer.rs#Lexer"
+      },
+      "position": {
+        "x": 402.5,
+        "y": 190
+      },
+      "group": "nodes",
+      "removed": false,
+      "selected": false,
+      "selectable": true,
+      "locked": false,
+      "grabbable": true,
+      "pannable": false,
+      "classes": "draft"
+    },
+    {
+      "data": {
+        "label": "Parsing happens\nin two passes",
+        "id": "f1a6c8d3-2e57-4b90-8c1f-6d3a9e4b7250"
+      },
+      "position": {
+        "x": 118.8671875,
+        "y": -42.3359375
+      },
+      "group": "nodes",
+      "removed": false,
+      "selected": false,
+      "selectable": true,
+      "locked": false,
+      "grabbable": true,
+      "pannable": false,
+      "classes": "comment"
+    }
+  ],
+  "edges": [
+    {
+      "data": {
+        "source": "c3d9a1e4-7f20-4b58-9e6d-2a8b5c0f1e93",
+        "target": "8e47b2c0-3a19-4f6d-a5e8-9d1c7b2f0634",
+        "id": "0d2b7e91-6c48-4a3f-9b05-e8f1c4a62d37",
+        "label": "uses"
+      },
+      "position": {
+        "x": 0,
+        "y": 0
+      },
+      "group": "edges",
+      "removed": false,
+      "selected": false,
+      "selectable": true,
+      "locked": false,
+      "grabbable": true,
+      "pannable": true,
+      "classes": ""
+    },
+    {
+      "data": {
+        "source": "f1a6c8d3-2e57-4b90-8c1f-6d3a9e4b7250",
+        "target": "c3d9a1e4-7f20-4b58-9e6d-2a8b5c0f1e93",
+        "id": "a7c5f0e2-1b83-4d69-b2e4-7f0a9d3c5e18"
+      },
+      "position": {
+        "x": 0,
+        "y": 0
+      },
+      "group": "edges",
+      "removed": false,
+      "selected": false,
+      "selectable": true,
+      "locked": false,
+      "grabbable": true,
+      "pannable": true,
+      "classes": "draft"
+    }
+  ]
+}"##;
+
+    #[test]
+    fn editor_export_round_trip() {
+        let mut graph = Graph::parse(EDITOR_EXPORT).unwrap();
+        assert_eq!((graph.nodes.len(), graph.edges.len()), (4, 2));
+        let entries = graph.nodes.iter().chain(&graph.edges);
+        assert!(entries.clone().all(|entry| entry.extra.is_empty()));
+        let children = entries.filter(|entry| entry.data.parent.is_some());
+        assert_eq!(children.count(), 2);
+
+        // Unchanged graph is written as it was loaded.
+        assert_eq!(graph.serialize().unwrap(), EDITOR_EXPORT);
+
+        // Written from scratch, data keys follow the field order, but no value
+        // is lost or changed.
+        let written = serde_json::to_string_pretty(&graph).unwrap();
+        assert!(written.contains("\"x\": 250,"));
+        let expected: Value = serde_json::from_str(EDITOR_EXPORT).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&written).unwrap(), expected);
+
+        graph.nodes[1].data.valid = Some(false);
+        assert_eq!(
+            graph.serialize().unwrap(),
+            EDITOR_EXPORT.replacen("\"valid\": true", "\"valid\": false", 1)
+        );
+    }
+
+    /// Hand-written elements with properties which Cytoscape plugins or other
+    /// tools may add next to `data`. The editor doesn't write these itself.
+    const EXTRA_FIELDS: &str = r##"{
+  "nodes": [
+    {
+      "data": {
+        "id": "core",
+        "label": "Core"
+      },
+      "position": {
+        "x": 140.5,
+        "y": 72.25
+      },
+      "group": "nodes",
+      "removed": false,
+      "selected": false,
+      "selectable": true,
+      "locked": false,
+      "grabbable": true,
+      "pannable": false,
+      "classes": "",
+      "scratch": {
+        "_layout": {
+          "pinned": true
+        }
+      }
+    },
+    {
+      "data": {
+        "id": "parser",
+        "parent": "core",
+        "label": "Parser",
+        "ref": "lsp://src/parser.rs?kind=struct#Parser",
+        "valid": true,
+        "location": "src/parser.rs:12",
+        "doc": "```rust\nstruct Parser\n```"
+      },
+      "position": {
+        "x": 0,
+        "y": 72.25
+      },
+      "group": "nodes",
+      "removed": false,
+      "selected": false,
+      "selectable": true,
+      "locked": false,
+      "grabbable": true,
+      "pannable": false,
+      "classes": "",
+      "style": {
+        "background-color": "#abc"
+      },
+      "renderedPosition": {
+        "x": 12.5,
+        "y": -3.75
+      }
+    }
+  ],
+  "edges": [
+    {
+      "data": {
+        "source": "parser",
+        "target": "core",
+        "id": "e1"
+      },
+      "position": {
+        "x": 0,
+        "y": 0
+      },
+      "group": "edges",
+      "removed": false,
+      "selected": false,
+      "selectable": true,
+      "locked": false,
+      "grabbable": true,
+      "pannable": true,
+      "classes": "",
+      "scratch": {}
+    }
+  ],
+  "zoom": 1.25
+}"##;
+
+    #[test]
+    fn extra_fields_round_trip() {
+        let graph = Graph::parse(EXTRA_FIELDS).unwrap();
+        assert_eq!(graph.nodes[0].extra["scratch"]["_layout"]["pinned"], true);
+        assert_eq!(graph.nodes[1].extra.len(), 2);
+        assert!(graph.edges[0].extra.contains_key("scratch"));
+
+        let saved = serde_json::to_string_pretty(&graph).unwrap();
+        assert_eq!(saved, EXTRA_FIELDS);
+        let again = Graph::parse(&saved).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&again).unwrap(), saved);
+    }
+
+    #[test]
+    fn extra_fields_after_update() {
+        let mut graph = Graph::parse(EXTRA_FIELDS).unwrap();
+        let parser = &mut graph.nodes[1];
+        parser.data.valid = Some(false);
+        parser.data.doc = None;
+
+        let saved: Value = serde_json::to_value(&graph).unwrap();
+        let mut expected: Value = serde_json::from_str(EXTRA_FIELDS).unwrap();
+        let data = &mut expected["nodes"][1]["data"];
+        data["valid"] = false.into();
+        data.as_object_mut().unwrap().shift_remove("doc");
+        assert_eq!(saved, expected);
+    }
+
+    #[test]
+    fn save_conflict() {
+        let dir = TempDir::new("save");
+        let path = dir.join("graph.json");
+        let original = r#"{"nodes": [{"data": {"id": "a", "ref": "file://a"}}]}"#;
+        fs::write(&path, original).unwrap();
+        let mut options = WriteArgs {
+            backup: true,
+            no_backup: false,
+            on_conflict: OnConflict::Refuse,
+        };
+
+        let mut graph = Graph::from_json(&path).unwrap();
+        graph.nodes[0].data.valid = Some(false);
+        graph.save(&path, &options).unwrap();
+        let saved = r#"{"nodes": [{"data": {"id": "a", "ref": "file://a", "valid": false}}]}"#;
+        assert_eq!(fs::read_to_string(&path).unwrap(), saved);
+        assert_eq!(
+            fs::read_to_string(dir.join("graph.json.bak")).unwrap(),
+            original
+        );
+
+        // File is changed by the editor after the first save.
+        let edited = r#"{"nodes": [{"data": {"id": "a", "ref": "file://a", "valid": false, "label": "A"}}]}"#;
+        fs::write(&path, edited).unwrap();
+        graph.nodes[0].data.valid = Some(true);
+        assert!(graph.save(&path, &options).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+
+        options.on_conflict = OnConflict::Merge;
+        graph.save(&path, &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            edited.replace("false", "true")
+        );
+
+        // Merged changes are kept, and the text is still patched in place.
+        assert_eq!(graph.nodes[0].data.label.as_deref(), Some("A"));
+        graph.nodes[0].data.valid = Some(false);
+        graph.save(&path, &options).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+        assert_eq!(fs::read_dir(&*dir).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,571 @@
+use std::collections::{HashMap, HashSet};
+
+use unwrap_or::unwrap_some_or;
+
+use crate::graph::Graph;
+
+/// Preferred distance between connected nodes.
+const SPACING: f64 = 100.0;
+
+/// Distance between layers of layered layout.
+const LAYER_SPACING: f64 = 120.0;
+
+/// Number of force simulation steps.
+const FORCE_ITERATIONS: usize = 300;
+
+/// Number of ordering sweeps in layered layout.
+const ORDER_SWEEPS: usize = 8;
+
+const GOLDEN_ANGLE: f64 = 2.399_963_229_728_653;
+
+/// Graph nodes prepared for positioning.
+///
+/// Only leaf nodes are positioned: compound parents are sized by the editor to
+/// fit their children, so parent positions are derived from the children
+/// after the layout is done.
+struct Nodes {
+    positions: Vec<(f64, f64)>,
+    movable: Vec<bool>,
+    leaf: Vec<bool>,
+    /// Index of direct parent node.
+    parent: Vec<Option<usize>>,
+    /// Edges between leaf nodes, edges attached to compound nodes are
+    /// redirected to their first leaf descendant.
+    edges: Vec<(usize, usize)>,
+}
+
+impl Nodes {
+    fn new(graph: &Graph, unplaced_only: bool) -> Self {
+        let index: HashMap<&str, usize> = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.data.id.as_str(), i))
+            .collect();
+        let parent: Vec<Option<usize>> = graph
+            .nodes
+            .iter()
+            .map(|node| {
+                node.data
+                    .parent
+                    .as_deref()
+                    .and_then(|p| index.get(p).copied())
+            })
+            .collect();
+        let mut leaf = vec![true; graph.nodes.len()];
+        for &p in parent.iter().flatten() {
+            leaf[p] = false;
+        }
+
+        let positions: Vec<(f64, f64)> = graph
+            .nodes
+            .iter()
+            .map(|node| (node.position.x, node.position.y))
+            .collect();
+        let movable = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let at_origin = node.position.x == 0.0 && node.position.y == 0.0;
+                leaf[i] && !node.locked && (!unplaced_only || at_origin)
+            })
+            .collect();
+
+        let representative = |mut i: usize| -> usize {
+            for _ in 0..parent.len() {
+                match parent.iter().position(|&p| p == Some(i)) {
+                    Some(child) => i = child,
+                    None => break,
+                }
+            }
+            i
+        };
+        let mut seen = HashSet::new();
+        let edges = graph
+            .edges
+            .iter()
+            .filter_map(|edge| {
+                let source = *index.get(edge.data.source.as_deref()?)?;
+                let target = *index.get(edge.data.target.as_deref()?)?;
+                Some((representative(source), representative(target)))
+            })
+            .filter(|&(source, target)| source != target && seen.insert((source, target)))
+            .collect();
+
+        Self {
+            positions,
+            movable,
+            leaf,
+            parent,
+            edges,
+        }
+    }
+
+    /// Write positions back to the graph and center compound parents over
+    /// their children.
+    fn apply(mut self, graph: &mut Graph) -> usize {
+        let mut moved = 0;
+        for (i, node) in graph.nodes.iter_mut().enumerate() {
+            if self.movable[i] {
+                node.position.x = self.positions[i].0.round();
+                node.position.y = self.positions[i].1.round();
+                moved += 1;
+            }
+        }
+
+        // Parents can be nested, so update deepest ones first.
+        let mut parents: Vec<usize> = (0..self.leaf.len()).filter(|&i| !self.leaf[i]).collect();
+        parents.sort_by_key(|&i| std::cmp::Reverse(self.depth(i)));
+        for p in parents {
+            let children: Vec<(f64, f64)> = (0..self.parent.len())
+                .filter(|&i| self.parent[i] == Some(p))
+                .map(|i| self.positions[i])
+                .collect();
+            if children.is_empty() || graph.nodes[p].locked {
+                continue;
+            }
+            let (min_x, max_x) = bounds(children.iter().map(|c| c.0));
+            let (min_y, max_y) = bounds(children.iter().map(|c| c.1));
+            self.positions[p] = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+            graph.nodes[p].position.x = self.positions[p].0.round();
+            graph.nodes[p].position.y = self.positions[p].1.round();
+        }
+        moved
+    }
+
+    fn depth(&self, mut i: usize) -> usize {
+        let mut depth = 0;
+        while let Some(p) = self.parent[i] {
+            depth += 1;
+            i = p;
+            if depth > self.parent.len() {
+                break;
+            }
+        }
+        depth
+    }
+}
+
+fn bounds(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    })
+}
+
+/// Force-directed (Fruchterman-Reingold) layout. Siblings within the same
+/// compound parent are pulled together, locked and skipped nodes stay in
+/// place but still affect the others. Returns number of positioned nodes.
+pub(crate) fn force(graph: &mut Graph, unplaced_only: bool) -> usize {
+    let mut nodes = Nodes::new(graph, unplaced_only);
+    let count = nodes.positions.len();
+    let active: Vec<usize> = (0..count).filter(|&i| nodes.leaf[i]).collect();
+
+    seed_positions(&mut nodes);
+
+    let mut temperature = SPACING * 2.0;
+    let cooling = temperature / FORCE_ITERATIONS as f64;
+
+    for _ in 0..FORCE_ITERATIONS {
+        let mut shift = vec![(0.0, 0.0); count];
+
+        for (a_slot, &a) in active.iter().enumerate() {
+            for &b in &active[a_slot + 1..] {
+                let (dx, dy, distance) = delta(nodes.positions[a], nodes.positions[b]);
+                let mut force = SPACING * SPACING / distance;
+                if nodes.parent[a] != nodes.parent[b] {
+                    force *= 2.0;
+                }
+                push(&mut shift[a], dx, dy, distance, force);
+                push(&mut shift[b], dx, dy, distance, -force);
+            }
+        }
+
+        for &(a, b) in &nodes.edges {
+            let (dx, dy, distance) = delta(nodes.positions[a], nodes.positions[b]);
+            let force = distance * distance / SPACING;
+            push(&mut shift[a], dx, dy, distance, -force);
+            push(&mut shift[b], dx, dy, distance, force);
+        }
+
+        let mut centers: HashMap<usize, (f64, f64, f64)> = HashMap::new();
+        for &i in &active {
+            if let Some(p) = nodes.parent[i] {
+                let center = centers.entry(p).or_default();
+                center.0 += nodes.positions[i].0;
+                center.1 += nodes.positions[i].1;
+                center.2 += 1.0;
+            }
+        }
+        for &i in &active {
+            let center = unwrap_some_or!(nodes.parent[i].and_then(|p| centers.get(&p)), {
+                continue;
+            });
+            let center = (center.0 / center.2, center.1 / center.2);
+            let (dx, dy, distance) = delta(nodes.positions[i], center);
+            push(
+                &mut shift[i],
+                dx,
+                dy,
+                distance,
+                -distance * distance / SPACING,
+            );
+        }
+
+        for &i in &active {
+            if !nodes.movable[i] {
+                continue;
+            }
+            let (dx, dy) = shift[i];
+            let length = dx.hypot(dy).max(f64::EPSILON);
+            let step = length.min(temperature);
+            nodes.positions[i].0 += dx / length * step;
+            nodes.positions[i].1 += dy / length * step;
+        }
+        temperature = (temperature - cooling).max(1.0);
+    }
+
+    nodes.apply(graph)
+}
+
+/// Give movable nodes distinct starting positions: next to their fixed
+/// neighbours if there are any, or on a spiral around the center otherwise.
+fn seed_positions(nodes: &mut Nodes) {
+    let fixed: Vec<usize> = (0..nodes.positions.len())
+        .filter(|&i| nodes.leaf[i] && !nodes.movable[i])
+        .collect();
+    let center = if fixed.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let count = fixed.len() as f64;
+        (
+            fixed.iter().map(|&i| nodes.positions[i].0).sum::<f64>() / count,
+            fixed.iter().map(|&i| nodes.positions[i].1).sum::<f64>() / count,
+        )
+    };
+
+    let mut step = 0;
+    for i in 0..nodes.positions.len() {
+        if !nodes.movable[i] {
+            continue;
+        }
+        let anchors: Vec<(f64, f64)> = nodes
+            .edges
+            .iter()
+            .filter_map(|&(a, b)| match (a == i, b == i) {
+                (true, _) if !nodes.movable[b] => Some(nodes.positions[b]),
+                (_, true) if !nodes.movable[a] => Some(nodes.positions[a]),
+                _ => None,
+            })
+            .collect();
+        let anchor = if anchors.is_empty() {
+            center
+        } else {
+            let count = anchors.len() as f64;
+            (
+                anchors.iter().map(|a| a.0).sum::<f64>() / count,
+                anchors.iter().map(|a| a.1).sum::<f64>() / count,
+            )
+        };
+
+        step += 1;
+        let angle = step as f64 * GOLDEN_ANGLE;
+        let radius = SPACING * (step as f64).sqrt();
+        nodes.positions[i] = (
+            anchor.0 + radius * angle.cos(),
+            anchor.1 + radius * angle.sin(),
+        );
+    }
+}
+
+/// Vector from `b` to `a` along with its length.
+fn delta(a: (f64, f64), b: (f64, f64)) -> (f64, f64, f64) {
+    let (dx, dy) = (a.0 - b.0, a.1 - b.1);
+    (dx, dy, dx.hypot(dy).max(1.0))
+}
+
+fn push(shift: &mut (f64, f64), dx: f64, dy: f64, distance: f64, force: f64) {
+    shift.0 += dx / distance * force;
+    shift.1 += dy / distance * force;
+}
+
+/// Layered (Sugiyama-style) layout: edges point downwards, nodes within each
+/// layer are ordered to reduce crossings while keeping siblings of the same
+/// compound parent next to each other. Nodes which are not positioned keep
+/// their place, and the laid out block is put below them.
+/// Returns number of positioned nodes.
+pub(crate) fn layered(graph: &mut Graph, unplaced_only: bool) -> usize {
+    let mut nodes = Nodes::new(graph, unplaced_only);
+    let count = nodes.positions.len();
+    let active: Vec<usize> = (0..count).filter(|&i| nodes.movable[i]).collect();
+    if active.is_empty() {
+        return 0;
+    }
+    let is_active: HashSet<usize> = active.iter().copied().collect();
+    let edges = acyclic_edges(&active, &nodes.edges, &is_active);
+
+    // Longest path layering in topological order.
+    let mut layer: HashMap<usize, usize> = active.iter().map(|&i| (i, 0)).collect();
+    let mut incoming: HashMap<usize, usize> = active.iter().map(|&i| (i, 0)).collect();
+    for &(_, target) in &edges {
+        *incoming.entry(target).or_default() += 1;
+    }
+    let mut queue: Vec<usize> = active
+        .iter()
+        .copied()
+        .filter(|i| incoming[i] == 0)
+        .collect();
+    while let Some(node) = queue.pop() {
+        for &(source, target) in &edges {
+            if source != node {
+                continue;
+            }
+            let next = layer[&source] + 1;
+            let current = layer.entry(target).or_default();
+            *current = (*current).max(next);
+            let remaining = incoming.entry(target).or_default();
+            *remaining -= 1;
+            if *remaining == 0 {
+                queue.push(target);
+            }
+        }
+    }
+
+    let depth = layer.values().copied().max().unwrap_or(0) + 1;
+    let mut layers: Vec<Vec<usize>> = vec![Vec::new(); depth];
+    for &i in &active {
+        layers[layer[&i]].push(i);
+    }
+    for nodes_in_layer in &mut layers {
+        nodes_in_layer.sort_by_key(|&i| (nodes.parent[i], i));
+    }
+
+    for sweep in 0..ORDER_SWEEPS {
+        let down = sweep % 2 == 0;
+        let order: Vec<usize> = if down {
+            (1..depth).collect()
+        } else {
+            (0..depth.saturating_sub(1)).rev().collect()
+        };
+        for l in order {
+            let reference = if down { l - 1 } else { l + 1 };
+            let slots: HashMap<usize, f64> = layers[reference]
+                .iter()
+                .enumerate()
+                .map(|(slot, &i)| (i, slot as f64))
+                .collect();
+            let barycenter = |i: usize, current: f64| {
+                let linked: Vec<f64> = edges
+                    .iter()
+                    .filter_map(|&(s, t)| match (s == i, t == i) {
+                        (true, _) => slots.get(&t).copied(),
+                        (_, true) => slots.get(&s).copied(),
+                        _ => None,
+                    })
+                    .collect();
+                if linked.is_empty() {
+                    current
+                } else {
+                    linked.iter().sum::<f64>() / linked.len() as f64
+                }
+            };
+
+            let values: HashMap<usize, f64> = layers[l]
+                .iter()
+                .enumerate()
+                .map(|(slot, &i)| (i, barycenter(i, slot as f64)))
+                .collect();
+            let mut groups: HashMap<Option<usize>, (f64, f64)> = HashMap::new();
+            for &i in &layers[l] {
+                let group = groups.entry(nodes.parent[i]).or_default();
+                group.0 += values[&i];
+                group.1 += 1.0;
+            }
+            let group_key = |i: usize| match nodes.parent[i] {
+                Some(p) => {
+                    let (sum, count) = groups[&Some(p)];
+                    sum / count
+                }
+                None => values[&i],
+            };
+            layers[l].sort_by(|&a, &b| {
+                group_key(a)
+                    .total_cmp(&group_key(b))
+                    .then(nodes.parent[a].cmp(&nodes.parent[b]))
+                    .then(values[&a].total_cmp(&values[&b]))
+            });
+        }
+    }
+
+    let fixed: Vec<(f64, f64)> = (0..count)
+        .filter(|&i| nodes.leaf[i] && !nodes.movable[i])
+        .map(|i| nodes.positions[i])
+        .collect();
+    let origin = if fixed.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let (min_x, max_x) = bounds(fixed.iter().map(|p| p.0));
+        let (_, max_y) = bounds(fixed.iter().map(|p| p.1));
+        ((min_x + max_x) / 2.0, max_y + LAYER_SPACING)
+    };
+
+    for (l, nodes_in_layer) in layers.iter().enumerate() {
+        let mut offsets = Vec::with_capacity(nodes_in_layer.len());
+        let mut x = 0.0;
+        for (slot, &i) in nodes_in_layer.iter().enumerate() {
+            if slot > 0 {
+                x += SPACING;
+                if nodes.parent[i] != nodes.parent[nodes_in_layer[slot - 1]] {
+                    x += SPACING / 2.0;
+                }
+            }
+            offsets.push(x);
+        }
+        let width = x;
+        for (&i, offset) in nodes_in_layer.iter().zip(offsets) {
+            nodes.positions[i] = (
+                origin.0 + offset - width / 2.0,
+                origin.1 + l as f64 * LAYER_SPACING,
+            );
+        }
+    }
+
+    nodes.apply(graph)
+}
+
+/// Keep edges between the given nodes, reversing the ones which close cycles.
+fn acyclic_edges(
+    active: &[usize],
+    edges: &[(usize, usize)],
+    is_active: &HashSet<usize>,
+) -> Vec<(usize, usize)> {
+    let edges: Vec<(usize, usize)> = edges
+        .iter()
+        .copied()
+        .filter(|(s, t)| is_active.contains(s) && is_active.contains(t))
+        .collect();
+
+    // 0 - not visited, 1 - on stack, 2 - done.
+    let mut state: HashMap<usize, u8> = HashMap::new();
+    let mut reversed = HashSet::new();
+    for &root in active {
+        if state.contains_key(&root) {
+            continue;
+        }
+        let mut stack = vec![(root, 0)];
+        state.insert(root, 1);
+        while let Some((node, next)) = stack.pop() {
+            let outgoing = edges
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| e.0 == node)
+                .nth(next);
+            let Some((edge, &(_, target))) = outgoing else {
+                state.insert(node, 2);
+                continue;
+            };
+            stack.push((node, next + 1));
+            match state.get(&target) {
+                None => {
+                    state.insert(target, 1);
+                    stack.push((target, 0));
+                }
+                Some(1) => {
+                    reversed.insert(edge);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    edges
+        .iter()
+        .enumerate()
+        .map(|(i, &(s, t))| {
+            if reversed.contains(&i) {
+                (t, s)
+            } else {
+                (s, t)
+            }
+        })
+        .filter(|&(s, t)| s != t)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::{Data, Entry, Graph};
+
+    fn graph(nodes: &[&str], edges: &[(&str, &str)]) -> Graph {
+        let mut graph = Graph::default();
+        for id in nodes {
+            graph.nodes.push(Entry::node(Data::new(*id)));
+        }
+        for (source, target) in edges {
+            let id = format!("{source}->{target}");
+            graph
+                .edges
+                .push(Entry::edge(Data::link(id, source, target)));
+        }
+        graph
+    }
+
+    fn position(graph: &Graph, index: usize) -> (f64, f64) {
+        (graph.nodes[index].position.x, graph.nodes[index].position.y)
+    }
+
+    #[test]
+    fn layered_chain() {
+        let mut graph = graph(&["a", "b", "c", "d"], &[("a", "b"), ("b", "c"), ("a", "d")]);
+        assert_eq!(super::layered(&mut graph, false), 4);
+
+        let (a, b, c, d) = (
+            position(&graph, 0),
+            position(&graph, 1),
+            position(&graph, 2),
+            position(&graph, 3),
+        );
+        assert!(a.1 < b.1 && b.1 < c.1);
+        assert_eq!(b.1, d.1);
+        assert_ne!(b.0, d.0);
+    }
+
+    #[test]
+    fn layered_cycle() {
+        let mut graph = graph(&["a", "b", "c"], &[("a", "b"), ("b", "c"), ("c", "a")]);
+        assert_eq!(super::layered(&mut graph, false), 3);
+        let ys: Vec<f64> = (0..3).map(|i| position(&graph, i).1).collect();
+        assert!(ys[0] < ys[1] && ys[1] < ys[2]);
+    }
+
+    #[test]
+    fn force_keeps_fixed_nodes() {
+        let mut graph = graph(&["a", "b", "c", "d"], &[("a", "b"), ("b", "c"), ("c", "d")]);
+        graph.nodes[0].locked = true;
+        graph.nodes[0].position.x = 500.0;
+        graph.nodes[1].position.x = 300.0;
+
+        assert_eq!(super::force(&mut graph, true), 2);
+        assert_eq!(position(&graph, 0), (500.0, 0.0));
+        assert_eq!(position(&graph, 1), (300.0, 0.0));
+
+        let c = position(&graph, 2);
+        let d = position(&graph, 3);
+        assert!((c.0 - d.0).hypot(c.1 - d.1) > 10.0);
+    }
+
+    #[test]
+    fn parents_follow_children() {
+        let mut graph = graph(&["group", "a", "b"], &[("a", "b")]);
+        graph.nodes[1].data.parent = Some("group".into());
+        graph.nodes[2].data.parent = Some("group".into());
+
+        assert_eq!(super::layered(&mut graph, false), 2);
+        let (a, b) = (position(&graph, 1), position(&graph, 2));
+        let group = position(&graph, 0);
+        assert_eq!(
+            group,
+            (((a.0 + b.0) / 2.0).round(), ((a.1 + b.1) / 2.0).round())
+        );
+    }
+}
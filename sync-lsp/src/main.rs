@@ -1,26 +1,67 @@
 mod args;
+mod browse;
+mod cargo;
 mod client;
+mod config;
+mod convert;
+mod detect;
+mod embed;
 mod graph;
+mod layout;
+mod merge;
 mod noderef;
+mod patch;
+mod pool;
+mod settings;
+mod show;
+#[cfg(test)]
+mod testing;
 
-use std::io::Write as _;
+use std::io::{IsTerminal as _, Write as _};
+use std::path::Path;
 use std::{fs, io};
 
 use anyhow::{Context as _, Result};
-use args::{Args, MakeRefArgs, Subcommand, VerifyArgs};
+use args::{
+    Args, BrowseArgs, CargoGraphArgs, CrateGraphArgs, EmbedArgs, ExportArgs, ExportFormat,
+    ImportArgs, ImportFormat, InitArgs, LayoutAlgorithm, LayoutArgs, MakeRefArgs, MergeArgs,
+    RenderArgs, ShowArgs, Subcommand, VerifyArgs, WriteArgs,
+};
 use clap::Parser as _;
-use log::{error, info};
+use config::Config;
+use convert::dot::DotGraph;
+use log::{error, info, warn};
 use noderef::{NodeRef, RefType};
+use pool::ClientPool;
+use settings::Settings;
 use unwrap_or::{unwrap_ok_or, unwrap_some_or};
 
 #[derive(Default)]
 struct Stats {
     checked_refs: usize,
+    ignored_refs: usize,
     missing_refs: usize,
+    /// References which couldn't be checked because of errors.
+    failed_refs: usize,
     updated_docs: usize,
     updated_locs: usize,
 }
 
+impl Stats {
+    /// Mark node as valid and update its resolved document and location.
+    fn apply(&mut self, data: &mut graph::Data, doc: String, location: Option<String>) {
+        if data.doc.as_ref() != Some(&doc) {
+            data.doc = Some(doc);
+            self.updated_docs += 1;
+        }
+        if location.is_some() && data.location != location {
+            data.location = location;
+            self.updated_locs += 1;
+        }
+        data.valid = Some(true);
+    }
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     let args = args::Args::parse();
@@ -29,6 +70,17 @@ async fn main() -> Result<()> {
     match &args.command {
         Subcommand::Verify(verify_args) => verify(&args, verify_args).await,
         Subcommand::MakeRef(make_ref_args) => make_ref(&args, make_ref_args).await,
+        Subcommand::CrateGraph(crate_graph_args) => crate_graph(&args, crate_graph_args).await,
+        Subcommand::CargoGraph(cargo_graph_args) => cargo_graph(&args, cargo_graph_args),
+        Subcommand::Merge(merge_args) => merge(&args, merge_args),
+        Subcommand::Layout(layout_args) => layout(&args, layout_args),
+        Subcommand::Render(render_args) => render(render_args),
+        Subcommand::Export(export_args) => export(export_args),
+        Subcommand::Import(import_args) => import(import_args),
+        Subcommand::Embed(embed_args) => embed(embed_args),
+        Subcommand::Show(show_args) => show(show_args),
+        Subcommand::Browse(browse_args) => browse(&args, browse_args).await,
+        Subcommand::Init(init_args) => init(init_args),
     }
 }
 
@@ -52,52 +104,138 @@ pub fn setup_logging(debug: bool) -> Result<(), log::SetLoggerError> {
         .apply()
 }
 
-/// Iterate over graph nodes and check the referenced entries.
+/// Value of the `--flag` and `--no-flag` pair, the default is used if
+/// neither of them is given.
+fn switch(on: bool, off: bool, default: bool) -> bool {
+    match (on, off) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => default,
+    }
+}
+
+/// Check the target graph, or all graphs of the project.
 async fn verify(args: &Args, verify: &VerifyArgs) -> Result<()> {
-    let mut graph = graph::Graph::from_json(&verify.target)?;
+    let config = project_config()?;
+    let config = config.as_ref();
+    let targets = match (&verify.target, config) {
+        (Some(target), _) => vec![target.to_path_buf()],
+        (None, Some(config)) if !config.graphs.is_empty() => config.graphs(),
+        _ => anyhow::bail!(
+            "No target specified, and no graphs listed in {}",
+            config::FILE_NAME
+        ),
+    };
+    let defaults = config.map(|config| &config.verify);
+    let update = switch(
+        verify.update,
+        verify.no_update,
+        defaults.is_some_and(|defaults| defaults.update),
+    );
+    let write = WriteArgs {
+        backup: switch(
+            args.write.backup,
+            args.write.no_backup,
+            defaults.is_some_and(|defaults| defaults.backup),
+        ),
+        no_backup: false,
+        on_conflict: args.write.on_conflict,
+    };
+
+    let (mut missing_refs, mut failed_refs) = (0, 0);
+    for target in &targets {
+        let stats = verify_graph(args, config, verify, target, update, &write).await?;
+        missing_refs += stats.missing_refs;
+        failed_refs += stats.failed_refs;
+    }
+    // Failed references are not recorded in the graph even with update.
+    if failed_refs > 0 || (missing_refs > 0 && !update) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Iterate over graph nodes and check the referenced entries. Returns the
+/// verification statistics.
+async fn verify_graph(
+    args: &Args,
+    config: Option<&Config>,
+    verify: &VerifyArgs,
+    target: &Path,
+    update: bool,
+    write: &WriteArgs,
+) -> Result<Stats> {
+    let mut graph = graph::Graph::from_json(target)?;
     info!(
         "Graph loaded, nodes: {}, edges: {}",
         graph.nodes.len(),
         graph.edges.len()
     );
 
-    let mut client = client::LspClient::new(&args.lsp, args.debug)?;
-    client.initialize().await?;
-    client.wait_index().await?;
-    info!("Indexing complete");
+    let settings = graph.settings.resolve(
+        Some(target),
+        config,
+        &args.server,
+        verify.root.as_deref(),
+        &verify.ignore,
+    )?;
+
+    // Servers for all references are started together.
+    let mut servers = Vec::new();
+    for node in &graph.nodes {
+        let node_ref = node.data.r#ref.as_deref().map(NodeRef::parse_ref);
+        if let Some(Ok(mut node_ref)) = node_ref
+            && matches!(node_ref.schema, RefType::Lsp)
+            && !settings.is_ignored(&node_ref.path)
+        {
+            node_ref.params.fill_defaults(&settings.default_params);
+            servers.extend(settings.server_for(&node_ref).ok());
+        }
+    }
+    let mut clients = ClientPool::new(&settings.root, args.debug);
+    clients.start(&servers).await?;
 
     let mut stats = Stats::default();
+    let mut cargo_workspace = None;
 
     for node in &mut graph.nodes {
         let ref_uri = unwrap_some_or!(&node.data.r#ref, { continue });
-        stats.checked_refs += 1;
 
-        let node_ref = unwrap_ok_or!(NodeRef::parse_ref(ref_uri), _, {
+        let mut node_ref = unwrap_ok_or!(NodeRef::parse_ref(ref_uri), _, {
+            stats.checked_refs += 1;
             error!("Unable to parse reference: {}", ref_uri);
             continue;
         });
+        if settings.is_ignored(&node_ref.path) {
+            stats.ignored_refs += 1;
+            continue;
+        }
+        stats.checked_refs += 1;
 
         match node_ref.schema {
             RefType::Lsp => {
-                let data = client.find_symbol(&node_ref).await?;
+                node_ref.params.fill_defaults(&settings.default_params);
+                let server = unwrap_ok_or!(settings.server_for(&node_ref), err, {
+                    error!("{err:#}: {}", ref_uri);
+                    stats.failed_refs += 1;
+                    continue;
+                });
+                let client = clients.get(server).await?;
+                let data = unwrap_ok_or!(client.find_symbol(&node_ref).await, err, {
+                    error!("Unable to check reference {}: {err:#}", ref_uri);
+                    stats.failed_refs += 1;
+                    continue;
+                });
                 if let Some(data) = data {
-                    if !verify.update {
+                    if !update {
                         continue;
                     }
 
-                    if node.data.doc.as_ref() != Some(&data.hover) {
-                        node.data.doc = Some(data.hover);
-                        stats.updated_docs += 1;
-                    }
-                    if node.data.location.as_ref() != Some(&data.location) {
-                        node.data.location = Some(data.location);
-                        stats.updated_locs += 1;
-                    }
-                    node.data.valid = Some(true);
+                    stats.apply(&mut node.data, data.hover, Some(data.location));
                 } else {
                     stats.missing_refs += 1;
                     error!("Reference not found: {}", ref_uri);
-                    if !verify.update {
+                    if !update {
                         continue;
                     }
 
@@ -105,16 +243,38 @@ async fn verify(args: &Args, verify: &VerifyArgs) -> Result<()> {
                 }
             }
             RefType::File => {
-                let exists = fs::metadata(node_ref.path).is_ok();
+                let exists = fs::metadata(settings.root.join(&node_ref.path)).is_ok();
                 if !exists {
                     error!("File reference not found: {}", ref_uri);
                     stats.missing_refs += 1;
                 }
 
-                if verify.update {
+                if update {
                     node.data.valid = Some(exists);
                 }
             }
+            RefType::Crate => {
+                let workspace =
+                    cargo_workspace.get_or_insert_with(|| cargo::Workspace::load(&settings.root));
+                match workspace.resolve(&node_ref) {
+                    Ok(Some(data)) => {
+                        if update {
+                            stats.apply(&mut node.data, data.doc, data.location);
+                        }
+                    }
+                    Ok(None) => {
+                        error!("Crate reference not found: {}", ref_uri);
+                        stats.missing_refs += 1;
+                        if update {
+                            node.data.valid = Some(false);
+                        }
+                    }
+                    Err(err) => {
+                        error!("Unable to resolve crate reference {}: {err:#}", ref_uri);
+                        stats.failed_refs += 1;
+                    }
+                }
+            }
             RefType::Unknown => {
                 error!("Unknown reference type: {}", ref_uri);
                 stats.missing_refs += 1;
@@ -122,9 +282,12 @@ async fn verify(args: &Args, verify: &VerifyArgs) -> Result<()> {
         }
     }
 
-    client.exit().await?;
+    clients.exit().await?;
 
     info!("References validated: {}", stats.checked_refs);
+    if stats.ignored_refs > 0 {
+        info!("References ignored: {}", stats.ignored_refs);
+    }
     if stats.updated_docs > 0 {
         info!("Docs updated: {}", stats.updated_docs);
     }
@@ -132,31 +295,27 @@ async fn verify(args: &Args, verify: &VerifyArgs) -> Result<()> {
         info!("Locations updated: {}", stats.updated_locs);
     }
 
+    if stats.failed_refs > 0 {
+        error!("Unable to check {} references", stats.failed_refs);
+    }
     if stats.missing_refs > 0 {
         error!("Found {} unresolved references", stats.missing_refs);
-
-        if !verify.update {
-            std::process::exit(1);
-        }
-    } else {
+    } else if stats.failed_refs == 0 {
         info!("All references resolved");
     }
 
-    if verify.update {
-        let output =
-            serde_json::to_string_pretty(&graph).context("Unable to serialize graph data")?;
-        fs::write(&verify.target, &output)?;
+    if update {
+        graph.save(target, write)?;
     }
 
-    Ok(())
+    Ok(stats)
 }
 
 /// Produce a reference to the given place in code.
 async fn make_ref(args: &Args, make_ref: &MakeRefArgs) -> Result<()> {
-    let mut client = client::LspClient::new(&args.lsp, args.debug)?;
-    client.initialize().await?;
-    client.wait_index().await?;
-    info!("Indexing complete");
+    let config = project_config()?;
+    let settings = Settings::default().resolve(None, config.as_ref(), &args.server, None, &[])?;
+    let mut client = pool::start(&settings.server, &settings.root, args.debug).await?;
 
     if let Some(target) = &make_ref.target {
         if let Some((path, line, char)) = extract_path(target) {
@@ -179,7 +338,7 @@ async fn make_ref(args: &Args, make_ref: &MakeRefArgs) -> Result<()> {
                 break;
             };
 
-            let (path, line, char) = unwrap_some_or!(extract_path(&input.trim()), {
+            let (path, line, char) = unwrap_some_or!(extract_path(input.trim()), {
                 error!("Unable to extract path");
                 continue;
             });
@@ -195,6 +354,270 @@ async fn make_ref(args: &Args, make_ref: &MakeRefArgs) -> Result<()> {
     Ok(())
 }
 
+/// Import rust-analyzer crate graph as a new graph file.
+async fn crate_graph(args: &Args, crate_graph: &CrateGraphArgs) -> Result<()> {
+    let config = project_config()?;
+    let settings = Settings::default().resolve(None, config.as_ref(), &args.server, None, &[])?;
+    let mut client = pool::start(&settings.server, &settings.root, args.debug).await?;
+
+    let dot = client.view_crate_graph(crate_graph.full).await?;
+    client.exit().await?;
+
+    let dot = DotGraph::parse(&dot).context("Unable to parse crate graph")?;
+    let cwd = std::env::current_dir()?;
+    let metadata = match cargo::Metadata::load(&cwd) {
+        Ok(metadata) => Some(metadata),
+        Err(err) => {
+            warn!("Manifest references are not resolved: {err:#}");
+            None
+        }
+    };
+
+    let mut graph = cargo::crate_graph(&dot, metadata.as_ref(), &cwd);
+    info!(
+        "Crate graph imported, nodes: {}, edges: {}",
+        graph.nodes.len(),
+        graph.edges.len()
+    );
+    graph.save(&crate_graph.output, &args.write)
+}
+
+/// Produce graph of workspace members and dependencies from cargo metadata.
+fn cargo_graph(args: &Args, cargo_graph: &CargoGraphArgs) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let metadata = cargo::Metadata::load(&cwd)?;
+
+    let mut graph = cargo::dependency_graph(&metadata, &cwd, cargo_graph.transitive);
+    info!(
+        "Dependency graph generated, nodes: {}, edges: {}",
+        graph.nodes.len(),
+        graph.edges.len()
+    );
+    graph.save(&cargo_graph.output, &args.write)
+}
+
+/// Add entries from another graph file without touching the existing layout.
+fn merge(args: &Args, merge: &MergeArgs) -> Result<()> {
+    let mut graph = graph::Graph::from_json(&merge.target)?;
+    let incoming = graph::Graph::from_json(&merge.source)?;
+
+    let summary = merge::merge(&mut graph, incoming);
+    info!("Nodes matched: {}", summary.matched_nodes);
+    info!("Nodes added: {}", summary.added_nodes.len());
+    for node in &summary.added_nodes {
+        info!("  + {node}");
+    }
+    info!("Edges added: {}", summary.added_edges.len());
+    for edge in &summary.added_edges {
+        info!("  + {edge}");
+    }
+
+    graph.save(merge.output.as_ref().unwrap_or(&merge.target), &args.write)
+}
+
+/// Assign positions to the graph nodes.
+fn layout(args: &Args, layout: &LayoutArgs) -> Result<()> {
+    let mut graph = graph::Graph::from_json(&layout.target)?;
+
+    let moved = match layout.algorithm {
+        LayoutAlgorithm::Force => layout::force(&mut graph, layout.unplaced),
+        LayoutAlgorithm::Layered => layout::layered(&mut graph, layout.unplaced),
+    };
+    info!("Nodes positioned: {moved}");
+
+    graph.save(
+        layout.output.as_ref().unwrap_or(&layout.target),
+        &args.write,
+    )
+}
+
+/// Draw the graph as SVG image.
+fn render(render: &RenderArgs) -> Result<()> {
+    let graph = graph::Graph::from_json(&render.target)?;
+    write_output(render.output.as_deref(), &convert::svg::render(&graph))
+}
+
+/// Convert the graph into another diagram format.
+fn export(export: &ExportArgs) -> Result<()> {
+    let graph = graph::Graph::from_json(&export.target)?;
+    let output = match export.format {
+        ExportFormat::Canvas => {
+            let canvas = convert::canvas::write(&graph);
+            serde_json::to_string_pretty(&canvas).context("Unable to serialize canvas")?
+        }
+        ExportFormat::Dot => convert::dot::write(&graph),
+        ExportFormat::Drawio => convert::drawio::write(&graph),
+        ExportFormat::Graphml => convert::graphml::write(&graph),
+        ExportFormat::Mermaid => convert::mermaid::write(&graph),
+        ExportFormat::Plantuml => convert::plantuml::write(&graph),
+    };
+    write_output(export.output.as_deref(), &output)
+}
+
+/// Convert diagram in another format into the graph.
+fn import(import: &ImportArgs) -> Result<()> {
+    let source = fs::read_to_string(&import.source).context("Unable to read diagram file")?;
+    let graph = match import.format {
+        ImportFormat::Canvas => {
+            let canvas = serde_json::from_str(&source).context("Unable to parse canvas")?;
+            convert::canvas::read(&canvas)
+        }
+        ImportFormat::Dot => convert::dot::read(&source)?,
+        ImportFormat::Graphml => convert::graphml::read(&source)?,
+        ImportFormat::Mermaid => convert::mermaid::read(&source)?,
+    };
+
+    let output = serde_json::to_string_pretty(&graph).context("Unable to serialize graph data")?;
+    write_output(import.output.as_deref(), &output)?;
+    // Logs share stdout with the graph written there.
+    if import.output.is_some() {
+        info!(
+            "Diagram imported, nodes: {}, edges: {}",
+            graph.nodes.len(),
+            graph.edges.len()
+        );
+    }
+    Ok(())
+}
+
+/// Update Mermaid diagrams embedded into Markdown documents.
+fn embed(embed: &EmbedArgs) -> Result<()> {
+    let mut stale = 0;
+    for target in &embed.targets {
+        let blocks = embed::process(target, embed.check)
+            .with_context(|| format!("Unable to process {}", target.display()))?;
+        for block in blocks.iter().filter(|block| block.stale) {
+            stale += 1;
+            if embed.check {
+                error!("Outdated diagram: {} ({})", target.display(), block.graph);
+            } else {
+                info!("Diagram updated: {} ({})", target.display(), block.graph);
+            }
+        }
+    }
+
+    if stale == 0 {
+        info!("All diagrams are up to date");
+    } else if embed.check {
+        error!("Found {stale} outdated diagrams");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Print the graph as nested boxes or as a tree.
+fn show(show: &ShowArgs) -> Result<()> {
+    let graph = graph::Graph::from_json(&show.target)?;
+    let charset = match show.ascii {
+        true => &show::ASCII,
+        false => &show::UNICODE,
+    };
+    // Only cut the lines when printing to terminal.
+    let width = show
+        .width
+        .unwrap_or_else(|| match io::stdout().is_terminal() {
+            true => crossterm::terminal::size().map_or(80, |(columns, _)| columns.into()),
+            false => usize::MAX,
+        });
+    let output = match show.tree {
+        true => show::tree(&graph, charset, width),
+        false => show::boxes(&graph, charset, width),
+    };
+    write_output(None, &output)
+}
+
+/// Interactive terminal browser for the graph.
+async fn browse(args: &Args, browse: &BrowseArgs) -> Result<()> {
+    let config = project_config()?;
+    let graph = graph::Graph::from_json(&browse.target)?;
+    if graph.nodes.is_empty() {
+        anyhow::bail!("Graph has no nodes to browse");
+    }
+    let settings = graph.settings.resolve(
+        Some(&browse.target),
+        config.as_ref(),
+        &args.server,
+        None,
+        &[],
+    )?;
+    let mut browser = browse::Browser::new(graph);
+    browse::run(&mut browser, &browse.target, &settings, &args.write).await?;
+    if browser.modified {
+        warn!("Resolved node states were not saved");
+    }
+    Ok(())
+}
+
+/// Create project configuration file in the current directory.
+fn init(init: &InitArgs) -> Result<()> {
+    let path = Path::new(config::FILE_NAME);
+    if path.exists() && !init.force {
+        anyhow::bail!(
+            "{} already exists, use --force to replace it",
+            path.display()
+        );
+    }
+    // Configuration which can't be parsed must not prevent replacing it.
+    let config = project_config().unwrap_or_else(|err| {
+        warn!("{err:#}");
+        None
+    });
+    let cwd = std::env::current_dir()?;
+    if let Some(config) = config.filter(|config| config.dir != cwd) {
+        warn!(
+            "Configuration in {} is shadowed for this directory",
+            config.dir.display()
+        );
+    }
+
+    let graphs = match init.graphs.is_empty() {
+        true => find_graphs()?,
+        false => init.graphs.clone(),
+    };
+    fs::write(path, config::scaffold(&graphs)).context("Unable to write configuration file")?;
+    info!(
+        "Created {} with graphs: {}",
+        path.display(),
+        graphs.join(", ")
+    );
+    Ok(())
+}
+
+/// Project configuration for the current directory, if there is one.
+fn project_config() -> Result<Option<Config>> {
+    Config::find(&std::env::current_dir()?)
+}
+
+/// Names of graph JSON files in the current directory.
+fn find_graphs() -> Result<Vec<String>> {
+    let mut graphs = Vec::new();
+    for entry in fs::read_dir(".")? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let is_graph = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| graph::Graph::parse(&text).ok())
+            .is_some_and(|graph| !graph.nodes.is_empty());
+        if let (true, Some(name)) = (is_graph, path.file_name()) {
+            graphs.push(name.to_string_lossy().into_owned());
+        }
+    }
+    graphs.sort();
+    Ok(graphs)
+}
+
+/// Write command output into the file, or to stdout if it's not specified.
+fn write_output(path: Option<&Path>, output: &str) -> Result<()> {
+    match path {
+        Some(path) => fs::write(path, output).context("Unable to write output file"),
+        None => io::stdout()
+            .write_all(output.as_bytes())
+            .context("Unable to write output"),
+    }
+}
+
 /// Extract line number and character number from the input parameter.
 /// Numbers are coverted to be 0-based to be compatible with LSP output.
 fn extract_path(full_path: &str) -> Option<(&str, u32, u32)> {
@@ -217,4 +640,68 @@ mod tests {
         assert_eq!(super::extract_path("src/main.rs:32"), None);
         assert_eq!(super::extract_path("src/main.rs:-32:-5"), None);
     }
+
+    #[test]
+    fn verify_switches() {
+        use clap::Parser as _;
+
+        let verify = |flags: &[&str], default| {
+            let args = super::Args::parse_from(
+                ["islands-sync-lsp", "verify", "graph.json"]
+                    .iter()
+                    .chain(flags),
+            );
+            let super::Subcommand::Verify(verify) = &args.command else {
+                unreachable!();
+            };
+            (
+                super::switch(verify.update, verify.no_update, default),
+                super::switch(args.write.backup, args.write.no_backup, default),
+            )
+        };
+        assert_eq!(verify(&[], false), (false, false));
+        assert_eq!(verify(&[], true), (true, true));
+        assert_eq!(
+            verify(&["--no-update", "--no-backup"], true),
+            (false, false)
+        );
+        assert_eq!(verify(&["--update", "--backup"], false), (true, true));
+        assert_eq!(verify(&["--update", "--no-update"], true), (false, true));
+    }
+
+    #[tokio::test]
+    async fn verify_counts() {
+        use clap::Parser as _;
+
+        let dir = crate::testing::TempDir::new("verify");
+        std::fs::write(dir.join("present.txt"), "").unwrap();
+        let target = dir.join("graph.json");
+        std::fs::write(
+            &target,
+            r#"{"nodes": [
+                {"data": {"id": "a", "ref": "file://present.txt"}},
+                {"data": {"id": "b", "ref": "file://missing.txt"}},
+                {"data": {"id": "c", "ref": "lsp://?server=unknown#main"}}
+            ]}"#,
+        )
+        .unwrap();
+
+        let args = super::Args::parse_from([
+            "islands-sync-lsp".as_ref(),
+            "verify".as_ref(),
+            target.as_os_str(),
+            "--root".as_ref(),
+            dir.as_os_str(),
+        ]);
+        let super::Subcommand::Verify(verify) = &args.command else {
+            unreachable!();
+        };
+        let stats = super::verify_graph(&args, None, verify, &target, false, &args.write)
+            .await
+            .unwrap();
+        // Unknown server profile is a configuration error, not a missing symbol.
+        assert_eq!(stats.checked_refs, 3);
+        assert_eq!(stats.missing_refs, 1);
+        assert_eq!(stats.failed_refs, 1);
+    }
 }
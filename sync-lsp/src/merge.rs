@@ -0,0 +1,341 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::graph::{Entry, Graph, Position};
+
+/// Distance between the placed node and its neighbours.
+const SPACING: f64 = 120.0;
+
+/// Minimal distance between placed node and any other node.
+const MIN_DISTANCE: f64 = 60.0;
+
+/// Lists of entries added during merge.
+#[derive(Default)]
+pub(crate) struct MergeSummary {
+    pub matched_nodes: usize,
+    pub added_nodes: Vec<String>,
+    pub added_edges: Vec<String>,
+}
+
+/// Merge incoming (usually generated) graph into the existing one.
+///
+/// Nodes are matched by `ref`, falling back to `id`, edges by `ref` or by
+/// their endpoints. Matched nodes keep the existing layout and user data, only
+/// the missing reference fields are filled in. Unmatched nodes and edges are
+/// appended under unique ids, with new nodes placed next to their already
+/// positioned neighbours.
+pub(crate) fn merge(graph: &mut Graph, incoming: Graph) -> MergeSummary {
+    let mut summary = MergeSummary::default();
+
+    let mut by_ref: HashMap<String, usize> = HashMap::new();
+    let mut by_id: HashMap<String, usize> = HashMap::new();
+    for (index, node) in graph.nodes.iter().enumerate() {
+        if let Some(r#ref) = &node.data.r#ref {
+            by_ref.entry(r#ref.clone()).or_insert(index);
+        }
+        by_id.insert(node.data.id.clone(), index);
+    }
+
+    // Incoming node id to id in the merged graph.
+    let mut ids: HashMap<String, String> = HashMap::new();
+    let mut taken: HashSet<String> = graph
+        .nodes
+        .iter()
+        .chain(&graph.edges)
+        .map(|entry| entry.data.id.clone())
+        .collect();
+    let first_added = graph.nodes.len();
+
+    for mut node in incoming.nodes {
+        let existing = node
+            .data
+            .r#ref
+            .as_ref()
+            .and_then(|r#ref| by_ref.get(r#ref))
+            .or_else(|| by_id.get(&node.data.id));
+
+        if let Some(&index) = existing {
+            let target = &mut graph.nodes[index].data;
+            fill(&mut target.label, node.data.label);
+            fill(&mut target.r#ref, node.data.r#ref);
+            fill(&mut target.valid, node.data.valid);
+            fill(&mut target.doc, node.data.doc);
+            fill(&mut target.location, node.data.location);
+            ids.insert(node.data.id, target.id.clone());
+            summary.matched_nodes += 1;
+            continue;
+        }
+
+        let id = unique_id(&mut taken, &node.data.id);
+        ids.insert(std::mem::replace(&mut node.data.id, id.clone()), id);
+        node.position = Position::default();
+        summary.added_nodes.push(
+            node.data
+                .label
+                .clone()
+                .unwrap_or_else(|| node.data.id.clone()),
+        );
+        graph.nodes.push(node);
+    }
+
+    for node in &mut graph.nodes[first_added..] {
+        if let Some(parent) = node.data.parent.as_mut()
+            && let Some(id) = ids.get(parent)
+        {
+            *parent = id.clone();
+        }
+    }
+
+    let mut links: HashSet<(String, String)> = graph
+        .edges
+        .iter()
+        .filter_map(|edge| Some((edge.data.source.clone()?, edge.data.target.clone()?)))
+        .collect();
+    let edge_refs: HashSet<String> = graph
+        .edges
+        .iter()
+        .filter_map(|edge| edge.data.r#ref.clone())
+        .collect();
+
+    for mut edge in incoming.edges {
+        let remap = |end: &Option<String>| end.as_ref().map(|id| ids.get(id).unwrap_or(id).clone());
+        let (source, target) = match (remap(&edge.data.source), remap(&edge.data.target)) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+
+        let matched = match &edge.data.r#ref {
+            Some(r#ref) => edge_refs.contains(r#ref),
+            None => false,
+        } || links.contains(&(source.clone(), target.clone()));
+        if matched {
+            continue;
+        }
+
+        links.insert((source.clone(), target.clone()));
+        edge.data.id = unique_id(&mut taken, &edge.data.id);
+        summary.added_edges.push(format!("{source} -> {target}"));
+        edge.data.source = Some(source);
+        edge.data.target = Some(target);
+        graph.edges.push(edge);
+    }
+
+    place_nodes(graph, first_added);
+    summary
+}
+
+/// Set the value only if it's not present yet.
+fn fill<T>(target: &mut Option<T>, value: Option<T>) {
+    if target.is_none() {
+        *target = value;
+    }
+}
+
+/// Produce id which doesn't collide with the existing entries.
+fn unique_id(taken: &mut HashSet<String>, id: &str) -> String {
+    let mut candidate = id.to_string();
+    let mut suffix = 1;
+    while taken.contains(&candidate) {
+        suffix += 1;
+        candidate = format!("{id}-{suffix}");
+    }
+    taken.insert(candidate.clone());
+    candidate
+}
+
+/// Position nodes starting from `first` index next to their placed neighbours
+/// (linked nodes and siblings within the same parent). Nodes without placed
+/// neighbours are put in a row below the existing graph.
+fn place_nodes(graph: &mut Graph, first: usize) {
+    let index: HashMap<&str, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.data.id.as_str(), i))
+        .collect();
+
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); graph.nodes.len()];
+    for edge in &graph.edges {
+        let source = edge.data.source.as_deref().and_then(|id| index.get(id));
+        let target = edge.data.target.as_deref().and_then(|id| index.get(id));
+        if let (Some(&source), Some(&target)) = (source, target) {
+            neighbours[source].push(target);
+            neighbours[target].push(source);
+        }
+    }
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let parent = parent_of(node);
+        for (j, other) in graph.nodes.iter().enumerate() {
+            if i != j && parent.is_some() && parent_of(other) == parent {
+                neighbours[i].push(j);
+            }
+        }
+    }
+
+    let mut placed: Vec<bool> = (0..graph.nodes.len()).map(|i| i < first).collect();
+    let mut positions: Vec<(f64, f64)> = graph
+        .nodes
+        .iter()
+        .map(|node| (node.position.x, node.position.y))
+        .collect();
+    let existing = &positions[..first];
+    let bottom = existing.iter().map(|&(_, y)| y).reduce(f64::max);
+    let left = existing.iter().map(|&(x, _)| x).reduce(f64::min);
+    let mut row = (left.unwrap_or(0.0), bottom.map_or(0.0, |y| y + SPACING));
+
+    let mut pending: Vec<usize> = (first..graph.nodes.len()).collect();
+    while !pending.is_empty() {
+        // Pick the node with the most placed neighbours to grow clusters outwards.
+        let (slot, &node) = pending
+            .iter()
+            .enumerate()
+            .max_by_key(|&(_, &node)| neighbours[node].iter().filter(|&&n| placed[n]).count())
+            .unwrap_or((0, &pending[0]));
+        pending.swap_remove(slot);
+
+        let anchors: Vec<(f64, f64)> = neighbours[node]
+            .iter()
+            .filter(|&&n| placed[n])
+            .map(|&n| positions[n])
+            .collect();
+
+        let position = if anchors.is_empty() {
+            let position = row;
+            row.0 += SPACING;
+            position
+        } else {
+            let count = anchors.len() as f64;
+            let center = (
+                anchors.iter().map(|a| a.0).sum::<f64>() / count,
+                anchors.iter().map(|a| a.1).sum::<f64>() / count,
+            );
+            free_spot(center, &positions, &placed)
+        };
+
+        positions[node] = position;
+        placed[node] = true;
+    }
+
+    for (node, &(x, y)) in graph.nodes.iter_mut().zip(&positions).skip(first) {
+        node.position.x = x;
+        node.position.y = y;
+    }
+}
+
+fn parent_of(node: &Entry) -> Option<&str> {
+    node.data.parent.as_deref()
+}
+
+/// Walk outwards around the center until a spot with no nodes nearby is found.
+fn free_spot(center: (f64, f64), positions: &[(f64, f64)], placed: &[bool]) -> (f64, f64) {
+    const GOLDEN_ANGLE: f64 = 2.399_963_229_728_653;
+
+    for step in 0..1000 {
+        let angle = step as f64 * GOLDEN_ANGLE;
+        let radius = SPACING + step as f64 * MIN_DISTANCE / 4.0;
+        let candidate = (
+            (center.0 + radius * angle.cos()).round(),
+            (center.1 + radius * angle.sin()).round(),
+        );
+        let free = positions.iter().zip(placed).all(|(&(x, y), &placed)| {
+            !placed || (x - candidate.0).hypot(y - candidate.1) >= MIN_DISTANCE
+        });
+        if free {
+            return candidate;
+        }
+    }
+    center
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::{Data, Entry, Graph};
+
+    fn node(id: &str, r#ref: Option<&str>, x: f64, y: f64) -> Entry {
+        let mut data = Data::new(id);
+        data.r#ref = r#ref.map(Into::into);
+        let mut entry = Entry::node(data);
+        entry.position.x = x;
+        entry.position.y = y;
+        entry
+    }
+
+    #[test]
+    fn merge_keeps_layout() {
+        let mut existing = Graph::default();
+        let mut a = node("a", Some("crate:a"), 100.0, 100.0);
+        a.classes = "draft".into();
+        a.data.note = Some("keep me".into());
+        existing.nodes.push(a);
+
+        let mut incoming = Graph::default();
+        let mut a = node("crate-a", Some("crate:a"), 0.0, 0.0);
+        a.data.doc = Some("**a** 1.0.0".into());
+        incoming.nodes.push(a);
+        incoming
+            .nodes
+            .push(node("crate-b", Some("crate:b"), 0.0, 0.0));
+        incoming.edges.push(Entry::edge(Data::link(
+            "crate-a->crate-b",
+            "crate-a",
+            "crate-b",
+        )));
+
+        let summary = super::merge(&mut existing, incoming);
+        assert_eq!(summary.matched_nodes, 1);
+        assert_eq!(summary.added_nodes, ["crate-b"]);
+        assert_eq!(summary.added_edges, ["a -> crate-b"]);
+
+        let a = &existing.nodes[0];
+        assert_eq!((a.position.x, a.position.y), (100.0, 100.0));
+        assert_eq!(a.classes, "draft");
+        assert_eq!(a.data.note.as_deref(), Some("keep me"));
+        assert_eq!(a.data.doc.as_deref(), Some("**a** 1.0.0"));
+
+        let b = &existing.nodes[1];
+        let distance = (b.position.x - 100.0).hypot(b.position.y - 100.0);
+        assert!((super::MIN_DISTANCE..=super::SPACING * 2.0).contains(&distance));
+
+        assert_eq!(existing.edges[0].data.source.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn merge_skips_known_edges() {
+        let mut existing = Graph::default();
+        existing.nodes.push(node("a", None, 0.0, 0.0));
+        existing.nodes.push(node("b", None, 100.0, 0.0));
+        existing.edges.push(Entry::edge(Data::link("e1", "a", "b")));
+
+        let mut incoming = Graph::default();
+        incoming.nodes.push(node("a", None, 0.0, 0.0));
+        incoming.nodes.push(node("b", None, 0.0, 0.0));
+        incoming.edges.push(Entry::edge(Data::link("e2", "a", "b")));
+
+        let summary = super::merge(&mut existing, incoming);
+        assert_eq!(summary.matched_nodes, 2);
+        assert!(summary.added_nodes.is_empty());
+        assert!(summary.added_edges.is_empty());
+        assert_eq!(existing.edges.len(), 1);
+    }
+
+    #[test]
+    fn merge_renames_colliding_edges() {
+        let mut existing = Graph::default();
+        existing.nodes.push(node("a", None, 0.0, 0.0));
+        existing.nodes.push(node("b", None, 100.0, 0.0));
+        existing.nodes.push(node("c", None, 200.0, 0.0));
+        existing.edges.push(Entry::edge(Data::link("e1", "a", "b")));
+
+        let mut incoming = Graph::default();
+        incoming.nodes.push(node("b", None, 0.0, 0.0));
+        incoming.nodes.push(node("c", None, 0.0, 0.0));
+        incoming.edges.push(Entry::edge(Data::link("e1", "b", "c")));
+
+        let summary = super::merge(&mut existing, incoming);
+        assert_eq!(summary.added_edges, ["b -> c"]);
+        assert_eq!(existing.edges.len(), 2);
+        let edge = &existing.edges[1].data;
+        assert_eq!(edge.id, "e1-2");
+        assert_eq!(edge.source.as_deref(), Some("b"));
+        assert_eq!(edge.target.as_deref(), Some("c"));
+    }
+}
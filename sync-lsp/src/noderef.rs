@@ -1,6 +1,6 @@
 use anyhow::Context as _;
 use async_lsp::lsp_types::SymbolKind;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use unwrap_or::unwrap_some_or;
 
 #[derive(Default)]
@@ -9,6 +9,9 @@ pub(crate) struct NodeRef {
     pub path: String,
     pub params: NodeRefParams,
     pub hash: String,
+
+    /// Version requirement of `crate:` reference.
+    pub version: Option<String>,
 }
 
 impl NodeRef {
@@ -28,6 +31,19 @@ impl NodeRef {
                     ..Default::default()
                 });
             }
+            "crate" => {
+                let path = path.strip_prefix("//").unwrap_or(path);
+                let (name, version) = match path.split_once('@') {
+                    Some((name, version)) => (name, Some(version.into())),
+                    None => (path, None),
+                };
+                return Ok(Self {
+                    schema: RefType::Crate,
+                    path: name.into(),
+                    version,
+                    ..Default::default()
+                });
+            }
             _ => return Ok(Self::default()),
         };
 
@@ -51,6 +67,7 @@ impl NodeRef {
             path: path.into(),
             params: NodeRefParams::from_str(params)?,
             hash: urlencoding::decode(hash)?.into(),
+            ..Default::default()
         })
     }
 }
@@ -59,18 +76,24 @@ impl NodeRef {
 pub(crate) enum RefType {
     Lsp,
     File,
+    Crate,
     #[default]
     Unknown,
 }
 
-#[derive(Default, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub(crate) struct NodeRefParams {
     /// Symbol kind.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub kind: Option<KindMarker>,
 
     /// Symbol container value.
-    #[expect(unused)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub container: Option<String>,
+
+    /// Name of the server profile to look the symbol up with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
 }
 
 impl NodeRefParams {
@@ -78,6 +101,21 @@ impl NodeRefParams {
         serde_urlencoded::from_str(line).context("Unable to parse reference")
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.kind.is_none() && self.container.is_none() && self.server.is_none()
+    }
+
+    /// Take the parameters which are not set from the defaults.
+    pub fn fill_defaults(&mut self, defaults: &Self) {
+        self.kind = self.kind.or(defaults.kind);
+        if self.container.is_none() {
+            self.container.clone_from(&defaults.container);
+        }
+        if self.server.is_none() {
+            self.server.clone_from(&defaults.server);
+        }
+    }
+
     /// Check if node reference matches the specific symbol kind.
     pub fn matches_kind(&self, kind: SymbolKind) -> bool {
         let match_kind = unwrap_some_or!(&self.kind, return true);
@@ -85,7 +123,7 @@ impl NodeRefParams {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) enum KindMarker {
     File,
@@ -117,7 +155,7 @@ pub(crate) enum KindMarker {
 }
 
 impl KindMarker {
-    pub fn to_kind(&self) -> SymbolKind {
+    pub fn to_kind(self) -> SymbolKind {
         match self {
             Self::File => SymbolKind::FILE,
             Self::Module => SymbolKind::MODULE,
@@ -149,17 +187,34 @@ impl KindMarker {
     }
 }
 
-#[test]
-fn parse_ref() {
-    let node_ref = NodeRef::parse_ref("lsp://src/main.rs?kind=function#main").unwrap();
-    assert!(matches!(node_ref.schema, RefType::Lsp));
-    assert!(matches!(node_ref.params.kind, Some(KindMarker::Function)));
-    assert_eq!(node_ref.path, "src/main.rs");
-    assert_eq!(node_ref.hash, "main");
-
-    let node_ref = NodeRef::parse_ref("lsp://src/main.rs?kind=function").unwrap();
-    assert!(matches!(node_ref.schema, RefType::Lsp));
-    assert!(matches!(node_ref.params.kind, Some(KindMarker::Function)));
-    assert_eq!(node_ref.path, "src/main.rs");
-    assert_eq!(node_ref.hash, "");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ref() {
+        let node_ref = NodeRef::parse_ref("lsp://src/main.rs?kind=function#main").unwrap();
+        assert!(matches!(node_ref.schema, RefType::Lsp));
+        assert!(matches!(node_ref.params.kind, Some(KindMarker::Function)));
+        assert_eq!(node_ref.path, "src/main.rs");
+        assert_eq!(node_ref.hash, "main");
+
+        let node_ref = NodeRef::parse_ref("lsp://src/main.rs?kind=function").unwrap();
+        assert!(matches!(node_ref.schema, RefType::Lsp));
+        assert!(matches!(node_ref.params.kind, Some(KindMarker::Function)));
+        assert_eq!(node_ref.path, "src/main.rs");
+        assert_eq!(node_ref.hash, "");
+    }
+
+    #[test]
+    fn parse_crate_ref() {
+        let node_ref = NodeRef::parse_ref("crate:serde@1").unwrap();
+        assert!(matches!(node_ref.schema, RefType::Crate));
+        assert_eq!(node_ref.path, "serde");
+        assert_eq!(node_ref.version.as_deref(), Some("1"));
+
+        let node_ref = NodeRef::parse_ref("crate:islands-sync-lsp").unwrap();
+        assert_eq!(node_ref.path, "islands-sync-lsp");
+        assert_eq!(node_ref.version, None);
+    }
 }
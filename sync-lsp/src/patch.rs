@@ -0,0 +1,476 @@
+//! Format-preserving updates of graph JSON text.
+
+use anyhow::{Context as _, bail};
+use log::warn;
+use serde_json::Value;
+
+use crate::graph::{Data, Graph};
+
+/// Data fields which are updated in place. Changes to anything else require
+/// the graph to be serialized again.
+pub(crate) const PATCHED_FIELDS: &[&str] = &["ref", "valid", "location", "doc"];
+
+/// Change of a single data field.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Change {
+    /// Either `nodes` or `edges`.
+    pub group: &'static str,
+    pub index: usize,
+    pub key: &'static str,
+    /// New value, or `None` to remove the field.
+    pub value: Option<Value>,
+}
+
+/// Compute the changes of patched fields between the two graphs. Returns
+/// `None` if the graphs differ in anything else.
+pub(crate) fn diff(original: &Graph, graph: &Graph) -> anyhow::Result<Option<Vec<Change>>> {
+    if stripped(original)? != stripped(graph)? {
+        return Ok(None);
+    }
+
+    let mut changes = Vec::new();
+    let groups = [
+        ("nodes", &original.nodes, &graph.nodes),
+        ("edges", &original.edges, &graph.edges),
+    ];
+    for (group, before, after) in groups {
+        for (index, (before, after)) in before.iter().zip(after).enumerate() {
+            let (before, after) = (fields(&before.data), fields(&after.data));
+            for (key, (old, new)) in PATCHED_FIELDS.iter().zip(before.into_iter().zip(after)) {
+                if old != new {
+                    changes.push(Change {
+                        group,
+                        index,
+                        key,
+                        value: new,
+                    });
+                }
+            }
+        }
+    }
+    Ok(Some(changes))
+}
+
+/// Apply changes to the JSON text, keeping the rest of it intact.
+pub(crate) fn apply(text: &str, changes: &[Change]) -> anyhow::Result<String> {
+    let root = Parser::new(text).document()?;
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+
+    for change in changes {
+        let data = root
+            .member(change.group)
+            .and_then(|entries| entries.element(change.index))
+            .and_then(|entry| entry.member("data"))
+            .with_context(|| format!("Missing {}[{}].data", change.group, change.index))?;
+        let members = match &data.kind {
+            Kind::Object(members) => members,
+            _ => bail!("{}[{}].data is not an object", change.group, change.index),
+        };
+        let position = members.iter().position(|member| member.key == change.key);
+
+        let value = change
+            .value
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        match (position, value) {
+            (Some(position), Some(value)) => {
+                let span = &members[position].value;
+                edits.push((span.start, span.end, value));
+            }
+            (Some(position), None) => {
+                let member = &members[position];
+                let (start, end) = match position {
+                    // Take the separator before the member along with it.
+                    0 if members.len() > 1 => (member.key_start, members[1].key_start),
+                    0 => (data.start + 1, data.end - 1),
+                    _ => (members[position - 1].value.end, member.value.end),
+                };
+                edits.push((start, end, String::new()));
+            }
+            (None, Some(value)) => {
+                let key = serde_json::to_string(change.key)?;
+                let (position, text) = match members.last() {
+                    Some(last) => {
+                        // Repeat the layout of the existing members.
+                        let indent = match members.len() {
+                            // Without a separator to copy, keep members apart.
+                            1 => match &text[data.start + 1..last.key_start] {
+                                "" => " ",
+                                indent => indent,
+                            },
+                            len => {
+                                let previous = members[len - 2].value.end;
+                                let gap = &text[previous..last.key_start];
+                                gap.trim_start().strip_prefix(',').unwrap_or(gap)
+                            }
+                        };
+                        let colon = &text[last.key_end..last.value.start];
+                        (last.value.end, format!(",{indent}{key}{colon}{value}"))
+                    }
+                    None => (data.start + 1, format!("{key}: {value}")),
+                };
+                edits.push((position, position, text));
+            }
+            (None, None) => {}
+        }
+    }
+
+    // Apply from the end, so that earlier offsets stay valid. Insertions at
+    // the same position keep the order of changes.
+    let mut edits: Vec<_> = edits.into_iter().enumerate().collect();
+    edits.sort_by_key(|(order, (start, _, _))| std::cmp::Reverse((*start, *order)));
+    let mut output = text.to_string();
+    for (_, (start, end, replacement)) in edits {
+        output.replace_range(start..end, &replacement);
+    }
+    Ok(output)
+}
+
+/// Update the original graph text with the changes made to the graph.
+/// Returns `None` if the graph can't be written as a patch.
+pub(crate) fn update(original: &str, graph: &Graph) -> anyhow::Result<Option<String>> {
+    let before = Graph::parse(original)?;
+    match diff(&before, graph)? {
+        Some(changes) => Ok(Some(apply(original, &changes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Apply the changes made to the graph since it was parsed from `base` on top
+/// of the `current` text, which was changed by someone else in the meantime.
+/// Entries are matched by id, since they may have been moved around.
+pub(crate) fn merge(base: &str, current: &str, graph: &Graph) -> anyhow::Result<String> {
+    let before = Graph::parse(base)?;
+    let theirs = Graph::parse(current)?;
+    let changes =
+        diff(&before, graph)?.context("Graph has changes besides reference check results")?;
+
+    let mut rebased = Vec::new();
+    for change in changes {
+        let (before_entries, their_entries) = match change.group {
+            "nodes" => (&before.nodes, &theirs.nodes),
+            _ => (&before.edges, &theirs.edges),
+        };
+        let base_data = &before_entries[change.index].data;
+        let index = match their_entries
+            .iter()
+            .position(|entry| entry.data.id == base_data.id)
+        {
+            Some(index) => index,
+            None => {
+                warn!("Entry {} was removed, its update is dropped", base_data.id);
+                continue;
+            }
+        };
+        let field = PATCHED_FIELDS
+            .iter()
+            .position(|key| *key == change.key)
+            .unwrap_or_default();
+        let their_value = &fields(&their_entries[index].data)[field];
+        if *their_value != fields(base_data)[field] && *their_value != change.value {
+            bail!(
+                "Entry {} has conflicting {} values",
+                base_data.id,
+                change.key
+            );
+        }
+        rebased.push(Change { index, ..change });
+    }
+    apply(current, &rebased)
+}
+
+/// Graph value without the patched fields.
+fn stripped(graph: &Graph) -> anyhow::Result<Value> {
+    let mut value = serde_json::to_value(graph)?;
+    for group in ["nodes", "edges"] {
+        let entries = match value.get_mut(group).and_then(Value::as_array_mut) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        for entry in entries {
+            if let Some(data) = entry.get_mut("data").and_then(Value::as_object_mut) {
+                for key in PATCHED_FIELDS {
+                    data.shift_remove(*key);
+                }
+            }
+        }
+    }
+    Ok(value)
+}
+
+/// Values of patched fields in the `PATCHED_FIELDS` order.
+fn fields(data: &Data) -> [Option<Value>; 4] {
+    [
+        data.r#ref.clone().map(Value::from),
+        data.valid.map(Value::from),
+        data.location.clone().map(Value::from),
+        data.doc.clone().map(Value::from),
+    ]
+}
+
+/// JSON value with its byte range in the text.
+struct Span {
+    start: usize,
+    end: usize,
+    kind: Kind,
+}
+
+enum Kind {
+    Object(Vec<Member>),
+    Array(Vec<Span>),
+    Scalar,
+}
+
+struct Member {
+    key: String,
+    key_start: usize,
+    key_end: usize,
+    value: Span,
+}
+
+impl Span {
+    fn member(&self, key: &str) -> Option<&Span> {
+        match &self.kind {
+            Kind::Object(members) => members
+                .iter()
+                .rev()
+                .find(|member| member.key == key)
+                .map(|member| &member.value),
+            _ => None,
+        }
+    }
+
+    fn element(&self, index: usize) -> Option<&Span> {
+        match &self.kind {
+            Kind::Array(elements) => elements.get(index),
+            _ => None,
+        }
+    }
+}
+
+/// Minimal JSON parser which only records value locations. The text is
+/// expected to be valid JSON, which is checked by the graph parser first.
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, position: 0 }
+    }
+
+    fn document(&mut self) -> anyhow::Result<Span> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        if self.position != self.text.len() {
+            bail!("Unexpected data after JSON value at {}", self.position);
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> anyhow::Result<Span> {
+        self.skip_whitespace();
+        let start = self.position;
+        let kind = match self.peek() {
+            Some(b'{') => {
+                self.position += 1;
+                let mut members = Vec::new();
+                while !self.close(b'}')? {
+                    self.skip_whitespace();
+                    let key_start = self.position;
+                    self.string()?;
+                    let key_end = self.position;
+                    let key = serde_json::from_str(&self.text[key_start..key_end])?;
+                    self.skip_whitespace();
+                    self.expect(b':')?;
+                    let value = self.value()?;
+                    members.push(Member {
+                        key,
+                        key_start,
+                        key_end,
+                        value,
+                    });
+                }
+                Kind::Object(members)
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut elements = Vec::new();
+                while !self.close(b']')? {
+                    elements.push(self.value()?);
+                }
+                Kind::Array(elements)
+            }
+            Some(b'"') => {
+                self.string()?;
+                Kind::Scalar
+            }
+            Some(_) => {
+                let rest = &self.text[self.position..];
+                let length = rest
+                    .find(|c: char| c.is_whitespace() || matches!(c, ',' | ']' | '}'))
+                    .unwrap_or(rest.len());
+                if length == 0 {
+                    bail!("Unexpected character at {}", self.position);
+                }
+                self.position += length;
+                Kind::Scalar
+            }
+            None => bail!("Unexpected end of JSON"),
+        };
+        Ok(Span {
+            start,
+            end: self.position,
+            kind,
+        })
+    }
+
+    /// Skip the separator, returning whether the container is closed.
+    fn close(&mut self, close: u8) -> anyhow::Result<bool> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == close => {
+                self.position += 1;
+                Ok(true)
+            }
+            Some(b',') => {
+                self.position += 1;
+                Ok(false)
+            }
+            Some(_) => Ok(false),
+            None => bail!("Unexpected end of JSON"),
+        }
+    }
+
+    fn string(&mut self) -> anyhow::Result<()> {
+        self.expect(b'"')?;
+        let bytes = self.text.as_bytes();
+        while let Some(&c) = bytes.get(self.position) {
+            self.position += 1;
+            match c {
+                b'\\' => self.position += 1,
+                b'"' => return Ok(()),
+                _ => {}
+            }
+        }
+        bail!("Unterminated string")
+    }
+
+    fn expect(&mut self, expected: u8) -> anyhow::Result<()> {
+        if self.peek() != Some(expected) {
+            bail!("Expected '{}' at {}", expected as char, self.position);
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hand-formatted graph: 4-space indent, inline objects and float notation
+    /// which the serializer wouldn't produce.
+    const GRAPH: &str = r#"{
+    "nodes": [
+        {"data": {"id": "a", "ref": "lsp://src/a.rs#A", "valid": false}, "position": {"x": 1.0, "y": 2.50}},
+        {
+            "data": {
+                "id": "b",
+                "ref": "file://README.md",
+                "doc": "old"
+            },
+            "position": {"x": 10, "y": -0.0}
+        },
+        {"data": {"id": "c"}, "position": {"x": 0, "y": 0}}
+    ],
+    "edges": [ {"data": {"id": "e", "source": "a", "target": "b"}} ]
+}
+"#;
+
+    #[test]
+    fn unchanged_round_trip() {
+        let graph = Graph::parse(GRAPH).unwrap();
+        assert_eq!(update(GRAPH, &graph).unwrap().as_deref(), Some(GRAPH));
+    }
+
+    #[test]
+    fn patch_fields() {
+        let mut graph = Graph::parse(GRAPH).unwrap();
+        let a = &mut graph.nodes[0].data;
+        a.valid = Some(true);
+        a.location = Some("src/a.rs:3".into());
+        a.doc = Some("Line \"one\"\nline two".into());
+        let b = &mut graph.nodes[1].data;
+        b.doc = None;
+        b.r#ref = Some("file://docs/README.md".into());
+        graph.nodes[2].data.valid = Some(false);
+
+        let expected = GRAPH
+            .replace(
+                r#""valid": false}"#,
+                r#""valid": true, "location": "src/a.rs:3", "doc": "Line \"one\"\nline two"}"#,
+            )
+            .replace(
+                "\"file://README.md\",\n                \"doc\": \"old\"",
+                "\"file://docs/README.md\"",
+            )
+            .replace(r#"{"id": "c"}"#, r#"{"id": "c", "valid": false}"#);
+        assert_eq!(update(GRAPH, &graph).unwrap().unwrap(), expected);
+    }
+
+    #[test]
+    fn remove_first_member() {
+        let text = r#"{"nodes": [{"data": {"ref": "x", "id": "a"}}], "edges": []}"#;
+        let mut graph = Graph::parse(text).unwrap();
+        graph.nodes[0].data.r#ref = None;
+        assert_eq!(
+            update(text, &graph).unwrap().unwrap(),
+            r#"{"nodes": [{"data": {"id": "a"}}], "edges": []}"#
+        );
+    }
+
+    #[test]
+    fn merge_concurrent_changes() {
+        let mut graph = Graph::parse(GRAPH).unwrap();
+        graph.nodes[0].data.valid = Some(true);
+        graph.nodes[1].data.doc = Some("new".into());
+
+        // Nodes were reordered and moved in the meantime.
+        let current = r#"{"nodes": [
+  {"data": {"id": "b", "ref": "file://README.md", "doc": "old"}, "position": {"x": 3, "y": 4}},
+  {"data": {"id": "a", "ref": "lsp://src/a.rs#A", "valid": false}}
+]}"#;
+        assert_eq!(
+            merge(GRAPH, current, &graph).unwrap(),
+            current
+                .replace(r#""old""#, r#""new""#)
+                .replace(r#""valid": false"#, r#""valid": true"#)
+        );
+
+        let current = current.replace(r#""old""#, r#""other""#);
+        assert!(merge(GRAPH, &current, &graph).is_err());
+    }
+
+    #[test]
+    fn other_changes_are_not_patched() {
+        let mut graph = Graph::parse(GRAPH).unwrap();
+        graph.nodes[0].position.x = 5.0;
+        assert_eq!(update(GRAPH, &graph).unwrap(), None);
+
+        let mut graph = Graph::parse(GRAPH).unwrap();
+        graph.nodes.pop();
+        assert_eq!(update(GRAPH, &graph).unwrap(), None);
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use futures::future::{join_all, try_join_all};
+use log::info;
+
+use crate::client::LspClient;
+use crate::settings::Server;
+
+/// Running LSP servers, one per server profile.
+pub(crate) struct ClientPool {
+    root: PathBuf,
+    debug: bool,
+    clients: Vec<(Server, LspClient)>,
+}
+
+impl ClientPool {
+    pub fn new(root: &Path, debug: bool) -> Self {
+        Self {
+            root: root.into(),
+            debug,
+            clients: Vec::new(),
+        }
+    }
+
+    pub fn is_running(&self, server: &Server) -> bool {
+        self.clients.iter().any(|(running, _)| running == server)
+    }
+
+    /// Start the servers which are not running yet. Servers are initialized
+    /// and index the workspace concurrently.
+    pub async fn start(&mut self, servers: &[&Server]) -> anyhow::Result<()> {
+        let mut pending: Vec<&Server> = Vec::new();
+        for &server in servers {
+            if !self.is_running(server) && !pending.contains(&server) {
+                pending.push(server);
+            }
+        }
+        let started = try_join_all(
+            pending
+                .iter()
+                .map(|server| start(server, &self.root, self.debug)),
+        )
+        .await?;
+        self.clients
+            .extend(pending.into_iter().cloned().zip(started));
+        Ok(())
+    }
+
+    /// Client for the server, started if it's not running yet.
+    pub async fn get(&mut self, server: &Server) -> anyhow::Result<&mut LspClient> {
+        self.start(&[server]).await?;
+        self.clients
+            .iter_mut()
+            .find(|(running, _)| running == server)
+            .map(|(_, client)| client)
+            .context("Server is not running")
+    }
+
+    /// Shut down all servers together. Every server is stopped even if some
+    /// of them fail.
+    pub async fn exit(self) -> anyhow::Result<()> {
+        let results = join_all(
+            self.clients
+                .into_iter()
+                .map(|(server, mut client)| async move {
+                    client
+                        .exit()
+                        .await
+                        .with_context(|| format!("Unable to stop {server}"))
+                }),
+        )
+        .await;
+        results.into_iter().collect()
+    }
+}
+
+/// Start LSP server and wait for it to index the workspace.
+pub(crate) async fn start(server: &Server, root: &Path, debug: bool) -> anyhow::Result<LspClient> {
+    info!("Starting {server}");
+    let mut client = LspClient::new(server, root, debug)?;
+    client.initialize().await?;
+    client
+        .wait_index()
+        .await
+        .with_context(|| format!("Unable to index the workspace with {server}"))?;
+    info!("Indexing complete: {server}");
+    Ok(client)
+}
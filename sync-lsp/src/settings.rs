@@ -0,0 +1,465 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
+use indexmap::IndexMap;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::args::ServerArgs;
+use crate::config::Config;
+use crate::detect;
+use crate::noderef::{NodeRef, NodeRefParams};
+
+/// Synchronization settings stored in the graph file.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Settings {
+    /// LSP server used for references.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<Server>,
+
+    /// Workspace root, relative to the graph file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+
+    /// Servers for the references with matching paths.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<PathOverride>,
+
+    /// Glob patterns of reference paths which are not checked.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+
+    /// Parameters used for `lsp://` references which don't specify them.
+    #[serde(default, skip_serializing_if = "NodeRefParams::is_empty")]
+    pub default_params: NodeRefParams,
+
+    #[serde(flatten)]
+    pub extra: IndexMap<String, Value>,
+}
+
+/// LSP server command and its launch options.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Server {
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+
+    /// Environment variables for the server process.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, String>,
+
+    /// Value passed with the `initialize` request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initialization_options: Option<Value>,
+
+    /// How to tell the workspace is indexed. Progress tokens are used if
+    /// `indexing_tokens` are given, all work done progress otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readiness: Option<Readiness>,
+
+    /// Progress tokens which mark the end of indexing. Rust-analyzer ones are
+    /// used if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexing_tokens: Vec<String>,
+
+    /// Milliseconds without work done progress events after which the
+    /// workspace is considered indexed, 1000 by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiet_period: Option<u64>,
+
+    /// Seconds to wait for the index, 600 by default. Zero waits forever.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready_timeout: Option<u64>,
+
+    /// Seconds to wait for a response, 30 by default. Zero waits forever.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout: Option<u64>,
+
+    /// Times a request is repeated when the server cancels it or reports
+    /// modified content, 3 by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_retries: Option<u32>,
+
+    /// Extensions of the referenced files handled by the server.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<String>,
+}
+
+/// Strategy telling when the server finished indexing the workspace.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Readiness {
+    /// End of work done progress with one of the indexing tokens.
+    Tokens,
+    /// No work done progress is running, and none began or ended for the
+    /// quiet period.
+    Progress,
+    /// Quiescent state reported with rust-analyzer `experimental/serverStatus`.
+    ServerStatus,
+}
+
+/// Server for references with paths matching the glob pattern.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PathOverride {
+    pub path: String,
+    pub server: Server,
+}
+
+/// Settings with command line overrides applied and paths resolved.
+pub(crate) struct Resolved {
+    pub server: Server,
+    /// Absolute workspace root.
+    pub root: PathBuf,
+    /// Named server profiles, empty if the server is given on command line.
+    profiles: IndexMap<String, Server>,
+    /// Whether the server is given on command line.
+    forced: bool,
+    overrides: Vec<(GlobMatcher, Server)>,
+    ignore: GlobSet,
+    pub default_params: NodeRefParams,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            readiness: Some(Readiness::Tokens),
+            ..Self::new("rust-analyzer")
+        }
+    }
+}
+
+impl Server {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            args: Vec::new(),
+            env: IndexMap::new(),
+            initialization_options: None,
+            readiness: None,
+            indexing_tokens: Vec::new(),
+            quiet_period: None,
+            ready_timeout: None,
+            request_timeout: None,
+            request_retries: None,
+            extensions: Vec::new(),
+        }
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        match (self.readiness, self.indexing_tokens.is_empty()) {
+            (Some(readiness), _) => readiness,
+            (None, false) => Readiness::Tokens,
+            (None, true) => Readiness::Progress,
+        }
+    }
+}
+
+/// Command line of the server.
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.command)?;
+        for arg in &self.args {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Settings {
+    pub fn is_empty(&self) -> bool {
+        self.server.is_none()
+            && self.root.is_none()
+            && self.overrides.is_empty()
+            && self.ignore.is_empty()
+            && self.default_params.is_empty()
+            && self.extra.is_empty()
+    }
+
+    /// Apply project configuration and command line overrides on top of the
+    /// settings. Command line flags take precedence over the graph settings,
+    /// and these over the project configuration.
+    ///
+    /// Relative root from settings is resolved against the `graph` file
+    /// directory, and the one from command line against the current directory.
+    /// Without either, the project directory is used.
+    ///
+    /// If no server is configured, it's detected from the project markers in
+    /// the workspace root.
+    pub fn resolve(
+        &self,
+        graph: Option<&Path>,
+        config: Option<&Config>,
+        server: &ServerArgs,
+        root: Option<&Path>,
+        ignore: &[String],
+    ) -> anyhow::Result<Resolved> {
+        let root = match (root, &self.root, graph, config) {
+            (Some(root), _, _, _) => root.to_path_buf(),
+            (None, Some(root), Some(graph), _) => graph
+                .parent()
+                .map_or_else(|| root.clone(), |dir| dir.join(root)),
+            (None, Some(root), None, _) => root.clone(),
+            (None, None, _, Some(config)) => config.dir.clone(),
+            (None, None, _, None) => std::env::current_dir()?,
+        };
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("Unable to find workspace root {}", root.display()))?;
+
+        let mut profiles = match (&server.lsp, config) {
+            (None, Some(config)) => config.servers.clone(),
+            _ => IndexMap::new(),
+        };
+        let configured = match &server.lsp {
+            Some(command) => Some(Server {
+                readiness: detect::readiness(command),
+                ..Server::new(command.as_ref())
+            }),
+            None => self
+                .server
+                .clone()
+                .or_else(|| config.and_then(Config::default_server).cloned()),
+        };
+        let mut default = match configured {
+            Some(server) => server,
+            None => {
+                // Other detected servers handle files of their languages. All
+                // of them can be selected by command name.
+                let path = std::env::var_os("PATH");
+                let detected = detect::detect(&root, path.as_deref());
+                let missing: Vec<_> = detected.missing.iter().map(ToString::to_string).collect();
+                let missing = missing.join(", ");
+                let default = match detected.servers.first() {
+                    Some(server) => {
+                        if !missing.is_empty() {
+                            warn!("No server in PATH for {missing}");
+                        }
+                        server.clone()
+                    }
+                    None if missing.is_empty() => {
+                        info!("No project markers found, using rust-analyzer");
+                        Server::default()
+                    }
+                    None => {
+                        warn!("No server in PATH for {missing}, using rust-analyzer");
+                        Server::default()
+                    }
+                };
+                for server in detected.servers {
+                    profiles.entry(server.command.clone()).or_insert(server);
+                }
+                default
+            }
+        };
+        if !server.lsp_arg.is_empty() {
+            default.args = server.lsp_arg.clone();
+        }
+
+        // Server given on command line is used for all references.
+        let mut overrides = Vec::new();
+        if server.lsp.is_none() {
+            for item in &self.overrides {
+                overrides.push((glob(&item.path)?.compile_matcher(), item.server.clone()));
+            }
+            for route in config.iter().flat_map(|config| &config.routes) {
+                let profile = config.and_then(|config| config.servers.get(&route.server));
+                let profile =
+                    profile.with_context(|| format!("Unknown server profile: {}", route.server))?;
+                overrides.push((glob(&route.path)?.compile_matcher(), profile.clone()));
+            }
+        }
+
+        let patterns = match (ignore.is_empty(), self.ignore.is_empty(), config) {
+            (false, _, _) => ignore,
+            (true, false, _) => &self.ignore,
+            (true, true, Some(config)) => &config.verify.ignore,
+            (true, true, None) => &[][..],
+        };
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(glob(pattern)?);
+        }
+
+        Ok(Resolved {
+            server: default,
+            root,
+            profiles,
+            forced: server.lsp.is_some(),
+            overrides,
+            ignore: builder.build()?,
+            default_params: self.default_params.clone(),
+        })
+    }
+}
+
+impl Resolved {
+    /// Server for the reference: the profile named with `server` parameter,
+    /// the first matching path override, the profile handling the file
+    /// extension, or the default one. Server given on command line is used
+    /// for all references.
+    pub fn server_for(&self, node_ref: &NodeRef) -> anyhow::Result<&Server> {
+        if self.forced {
+            return Ok(&self.server);
+        }
+        if let Some(name) = &node_ref.params.server {
+            return self
+                .profiles
+                .get(name)
+                .with_context(|| format!("Unknown server profile: {name}"));
+        }
+        if let Some((_, server)) = self
+            .overrides
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(&node_ref.path))
+        {
+            return Ok(server);
+        }
+
+        let extension = Path::new(&node_ref.path)
+            .extension()
+            .and_then(|extension| extension.to_str());
+        let handles = |server: &Server| {
+            extension.is_some_and(|extension| server.extensions.iter().any(|e| e == extension))
+        };
+        if handles(&self.server) {
+            return Ok(&self.server);
+        }
+        Ok(self
+            .profiles
+            .values()
+            .find(|server| handles(server))
+            .unwrap_or(&self.server))
+    }
+
+    pub fn is_ignored(&self, path: &str) -> bool {
+        self.ignore.is_match(path)
+    }
+}
+
+fn glob(pattern: &str) -> anyhow::Result<Glob> {
+    Glob::new(pattern).with_context(|| format!("Invalid path pattern: {pattern}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_settings() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+            "server": {"command": "ra-multiplex", "args": ["client"]},
+            "root": ".",
+            "overrides": [{"path": "web/**", "server": {"command": "tsserver"}}],
+            "ignore": ["target/**"],
+            "defaultParams": {"kind": "struct"},
+            "theme": "dark"
+        }"#,
+        )
+        .unwrap();
+        let graph = std::env::current_dir().unwrap().join("graph.json");
+        let node_ref = |r#ref: &str| NodeRef::parse_ref(r#ref).unwrap();
+
+        let resolved = settings
+            .resolve(Some(&graph), None, &ServerArgs::default(), None, &[])
+            .unwrap();
+        assert_eq!(
+            resolved.root,
+            std::env::current_dir().unwrap().canonicalize().unwrap()
+        );
+        let server = resolved
+            .server_for(&node_ref("lsp://src/main.rs#main"))
+            .unwrap();
+        assert_eq!(server.command, "ra-multiplex");
+        assert_eq!(server.args, ["client"]);
+        let server = resolved
+            .server_for(&node_ref("lsp://web/app/main.ts#App"))
+            .unwrap();
+        assert_eq!(server.command, "tsserver");
+        assert!(resolved.is_ignored("target/debug/build.rs"));
+        assert!(!resolved.is_ignored("src/target.rs"));
+        assert!(resolved.default_params.kind.is_some());
+
+        // Command line flags take precedence.
+        let server = ServerArgs {
+            lsp: Some("rust-analyzer".into()),
+            lsp_arg: Vec::new(),
+        };
+        let resolved = settings
+            .resolve(Some(&graph), None, &server, None, &["src/**".into()])
+            .unwrap();
+        let server = resolved
+            .server_for(&node_ref("lsp://web/app/main.ts#App"))
+            .unwrap();
+        assert_eq!(server.command, "rust-analyzer");
+        assert!(server.args.is_empty());
+        assert_eq!(server.readiness(), Readiness::Tokens);
+        assert!(resolved.is_ignored("src/main.rs"));
+        assert!(!resolved.is_ignored("target/debug/build.rs"));
+        let server = resolved
+            .server_for(&node_ref("lsp://?server=ts#App"))
+            .unwrap();
+        assert_eq!(server.command, "rust-analyzer");
+
+        // Profile can't be selected when there are none.
+        let resolved = settings
+            .resolve(Some(&graph), None, &ServerArgs::default(), None, &[])
+            .unwrap();
+        assert!(
+            resolved
+                .server_for(&node_ref("lsp://?server=ts#App"))
+                .is_err()
+        );
+
+        let json = serde_json::to_value(&settings).unwrap();
+        assert_eq!(json["theme"], "dark");
+        assert_eq!(json["defaultParams"]["kind"], "struct");
+    }
+
+    #[test]
+    fn route_by_extension_and_param() {
+        let mut config = Config::parse(
+            r#"
+server = "rust"
+
+[servers.rust]
+command = "rust-analyzer"
+extensions = ["rs"]
+
+[servers.ts]
+command = "typescript-language-server"
+extensions = ["ts", "tsx"]
+
+[servers.python]
+command = "pylsp"
+extensions = ["py"]
+"#,
+        )
+        .unwrap();
+        config.dir = std::env::current_dir().unwrap();
+        let resolved = Settings::default()
+            .resolve(None, Some(&config), &ServerArgs::default(), None, &[])
+            .unwrap();
+        let command = |r#ref: &str| {
+            let node_ref = NodeRef::parse_ref(r#ref).unwrap();
+            resolved
+                .server_for(&node_ref)
+                .map(|server| server.command.clone())
+        };
+
+        assert_eq!(command("lsp://src/main.rs#main").unwrap(), "rust-analyzer");
+        assert_eq!(
+            command("lsp://editor/src/App.tsx#App").unwrap(),
+            "typescript-language-server"
+        );
+        assert_eq!(command("lsp://tools/gen.py#main").unwrap(), "pylsp");
+        assert_eq!(command("lsp://#Graph").unwrap(), "rust-analyzer");
+        assert_eq!(command("lsp://?server=python#main").unwrap(), "pylsp");
+        assert!(command("lsp://?server=go#main").is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+
+use crate::convert::{children, roots};
+use crate::graph::{Data, Graph};
+
+/// Characters used to draw the diagram.
+pub(crate) struct Charset {
+    pub horizontal: &'static str,
+    pub vertical: &'static str,
+    pub top_left: &'static str,
+    pub top_right: &'static str,
+    pub bottom_left: &'static str,
+    pub bottom_right: &'static str,
+    pub branch: &'static str,
+    pub last_branch: &'static str,
+    pub arrow: &'static str,
+    pub ellipsis: &'static str,
+    pub valid: &'static str,
+    pub invalid: &'static str,
+    pub unchecked: &'static str,
+    pub plain: &'static str,
+}
+
+pub(crate) const UNICODE: Charset = Charset {
+    horizontal: "─",
+    vertical: "│",
+    top_left: "┌",
+    top_right: "┐",
+    bottom_left: "└",
+    bottom_right: "┘",
+    branch: "├── ",
+    last_branch: "└── ",
+    arrow: "→",
+    ellipsis: "…",
+    valid: "✓",
+    invalid: "✗",
+    unchecked: "?",
+    plain: "•",
+};
+
+pub(crate) const ASCII: Charset = Charset {
+    horizontal: "-",
+    vertical: "|",
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    branch: "|-- ",
+    last_branch: "`-- ",
+    arrow: "->",
+    ellipsis: "~",
+    valid: "v",
+    invalid: "x",
+    unchecked: "?",
+    plain: "*",
+};
+
+/// Node with its nested nodes, prepared for printing.
+struct Item {
+    header: String,
+    location: Option<String>,
+    /// Outgoing edges.
+    edges: Vec<String>,
+    children: Vec<Item>,
+}
+
+/// Print compound nodes as nested boxes. Each node is followed by the list of
+/// its outgoing edges. Lines are cut to fit into `width` columns.
+pub(crate) fn boxes(graph: &Graph, charset: &Charset, width: usize) -> String {
+    let mut lines = Vec::new();
+    for item in items(graph, charset) {
+        box_lines(&item, charset, width, &mut lines);
+    }
+    join(lines)
+}
+
+/// Print nodes as indented tree, following the compound node nesting.
+pub(crate) fn tree(graph: &Graph, charset: &Charset, width: usize) -> String {
+    let mut lines = Vec::new();
+    for item in items(graph, charset) {
+        tree_lines(&item, charset, width, "", "", &mut lines);
+    }
+    join(lines)
+}
+
+fn items(graph: &Graph, charset: &Charset) -> Vec<Item> {
+    let children = children(graph);
+    let labels: HashMap<&str, &str> = graph
+        .nodes
+        .iter()
+        .map(|node| (node.data.id.as_str(), label(&node.data)))
+        .collect();
+    let mut edges: HashMap<&str, Vec<String>> = HashMap::new();
+    for edge in graph.edges.iter().filter(|edge| !edge.removed) {
+        let (source, target) = match (&edge.data.source, &edge.data.target) {
+            (Some(source), Some(target)) => (source.as_str(), target.as_str()),
+            _ => continue,
+        };
+        let target = single_line(labels.get(target).copied().unwrap_or(target));
+        let line = match &edge.data.label {
+            Some(label) => format!("{} {target} ({})", charset.arrow, single_line(label)),
+            None => format!("{} {target}", charset.arrow),
+        };
+        edges.entry(source).or_default().push(line);
+    }
+
+    fn item(
+        graph: &Graph,
+        index: usize,
+        children: &HashMap<&str, Vec<usize>>,
+        edges: &mut HashMap<&str, Vec<String>>,
+        charset: &Charset,
+        depth: usize,
+    ) -> Item {
+        let data = &graph.nodes[index].data;
+        let nested = match children.get(data.id.as_str()) {
+            Some(nested) if depth <= graph.nodes.len() => nested.as_slice(),
+            _ => &[],
+        };
+        let marker = match (data.valid, &data.r#ref) {
+            (Some(true), _) => charset.valid,
+            (Some(false), _) => charset.invalid,
+            (None, Some(_)) => charset.unchecked,
+            (None, None) if nested.is_empty() => charset.plain,
+            (None, None) => "",
+        };
+        let header = match marker {
+            "" => single_line(label(data)),
+            marker => format!("{marker} {}", single_line(label(data))),
+        };
+        Item {
+            header,
+            location: data.location.clone(),
+            edges: edges.remove(data.id.as_str()).unwrap_or_default(),
+            children: nested
+                .iter()
+                .map(|&child| item(graph, child, children, edges, charset, depth + 1))
+                .collect(),
+        }
+    }
+
+    roots(graph)
+        .into_iter()
+        .map(|index| item(graph, index, &children, &mut edges, charset, 0))
+        .collect()
+}
+
+/// Node header with location, and its edges. Location is moved to a separate
+/// line when it doesn't fit.
+fn detail_lines(item: &Item, charset: &Charset, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    match &item.location {
+        Some(location) if columns(&item.header) + 2 + columns(location) <= width => {
+            lines.push(format!("{}  {location}", item.header));
+        }
+        Some(location) => {
+            lines.push(item.header.clone());
+            lines.push(format!("  {location}"));
+        }
+        None => lines.push(item.header.clone()),
+    }
+    lines.extend(item.edges.iter().map(|edge| format!("  {edge}")));
+    lines
+        .into_iter()
+        .map(|line| fit(&line, width, charset))
+        .collect()
+}
+
+fn box_lines(item: &Item, charset: &Charset, width: usize, out: &mut Vec<String>) {
+    // Box needs space for borders, padding and a bit of content.
+    if item.children.is_empty() || width < 8 {
+        out.extend(detail_lines(item, charset, width));
+        for child in &item.children {
+            box_lines(child, charset, width, out);
+        }
+        return;
+    }
+
+    let inner_width = width - 4;
+    let title = fit(&item.header, inner_width - 2, charset);
+    let mut details = detail_lines(item, charset, inner_width);
+    details.remove(0);
+    let mut inner = details;
+    for child in &item.children {
+        box_lines(child, charset, inner_width, &mut inner);
+    }
+
+    let content = inner
+        .iter()
+        .map(|line| columns(line))
+        .chain([columns(&title) + 2])
+        .max()
+        .unwrap_or(0)
+        .min(inner_width);
+    let h = charset.horizontal;
+    out.push(format!(
+        "{}{h} {title} {}{}",
+        charset.top_left,
+        h.repeat(content - columns(&title) - 1),
+        charset.top_right
+    ));
+    for line in inner {
+        let padding = " ".repeat(content - columns(&line));
+        out.push(format!(
+            "{} {line}{padding} {}",
+            charset.vertical, charset.vertical
+        ));
+    }
+    out.push(format!(
+        "{}{}{}",
+        charset.bottom_left,
+        h.repeat(content + 2),
+        charset.bottom_right
+    ));
+}
+
+fn tree_lines(
+    item: &Item,
+    charset: &Charset,
+    width: usize,
+    first_prefix: &str,
+    prefix: &str,
+    out: &mut Vec<String>,
+) {
+    let detail_prefix = match item.children.is_empty() {
+        true => format!("{prefix}  "),
+        false => format!("{prefix}{}   ", charset.vertical),
+    };
+    let available = width.saturating_sub(columns(&detail_prefix)).max(1);
+    let mut details = detail_lines(item, charset, available).into_iter();
+    if let Some(header) = details.next() {
+        out.push(fit(&format!("{first_prefix}{header}"), width, charset));
+    }
+    for line in details {
+        // Details are indented by two columns already.
+        let line = line.strip_prefix("  ").unwrap_or(&line);
+        out.push(format!("{detail_prefix}{line}"));
+    }
+
+    let continuation = " ".repeat(columns(charset.branch) - 1);
+    for (index, child) in item.children.iter().enumerate() {
+        let last = index + 1 == item.children.len();
+        let (branch, rest) = match last {
+            true => (charset.last_branch, format!("{prefix} {continuation}")),
+            false => (
+                charset.branch,
+                format!("{prefix}{}{continuation}", charset.vertical),
+            ),
+        };
+        tree_lines(
+            child,
+            charset,
+            width,
+            &format!("{prefix}{branch}"),
+            &rest,
+            out,
+        );
+    }
+}
+
+fn label(data: &Data) -> &str {
+    data.label.as_deref().unwrap_or(&data.id)
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Display width of the text. Every character is treated as a single column.
+fn columns(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Cut the text to fit into the given width.
+fn fit(text: &str, width: usize, charset: &Charset) -> String {
+    if columns(text) <= width {
+        return text.into();
+    }
+    let keep = width.saturating_sub(columns(charset.ellipsis));
+    let mut out: String = text.chars().take(keep).collect();
+    out.push_str(charset.ellipsis);
+    out
+}
+
+fn join(lines: Vec<String>) -> String {
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Entry;
+
+    fn graph() -> Graph {
+        let mut graph = Graph::default();
+        let mut core = Data::new("core");
+        core.label = Some("Core".into());
+        graph.nodes.push(Entry::node(core));
+
+        let mut parser = Data::new("parser");
+        parser.parent = Some("core".into());
+        parser.label = Some("Parser".into());
+        parser.r#ref = Some("lsp://src/parser.rs#Parser".into());
+        parser.valid = Some(true);
+        parser.location = Some("src/parser.rs:12".into());
+        graph.nodes.push(Entry::node(parser));
+
+        let mut lexer = Data::new("lexer");
+        lexer.parent = Some("core".into());
+        lexer.label = Some("Lexer".into());
+        lexer.r#ref = Some("lsp://src/lexer.rs#Lexer".into());
+        lexer.valid = Some(false);
+        graph.nodes.push(Entry::node(lexer));
+
+        let mut main = Data::new("main");
+        main.r#ref = Some("lsp://src/main.rs#main".into());
+        graph.nodes.push(Entry::node(main));
+
+        let mut edge = Data::link("parser->lexer", "parser", "lexer");
+        edge.label = Some("uses".into());
+        graph.edges.push(Entry::edge(edge));
+        graph
+            .edges
+            .push(Entry::edge(Data::link("main->core", "main", "core")));
+        graph
+    }
+
+    #[test]
+    fn render_boxes() {
+        assert_eq!(
+            boxes(&graph(), &UNICODE, 80),
+            "\
+┌─ Core ─────────────────────┐
+│ ✓ Parser  src/parser.rs:12 │
+│   → Lexer (uses)           │
+│ ✗ Lexer                    │
+└────────────────────────────┘
+? main
+  → Core
+"
+        );
+        assert_eq!(
+            boxes(&graph(), &ASCII, 20),
+            "\
++- Core -----------+
+| v Parser         |
+|   src/parser.rs~ |
+|   -> Lexer (use~ |
+| x Lexer          |
++------------------+
+? main
+  -> Core
+"
+        );
+    }
+
+    #[test]
+    fn render_tree() {
+        assert_eq!(
+            tree(&graph(), &UNICODE, 80),
+            "\
+Core
+├── ✓ Parser  src/parser.rs:12
+│     → Lexer (uses)
+└── ✗ Lexer
+? main
+  → Core
+"
+        );
+    }
+
+    #[test]
+    fn render_dangling_parent() {
+        let mut graph = graph();
+        graph.nodes[3].data.parent = Some("missing".into());
+        graph.nodes[0].removed = true;
+        assert_eq!(
+            tree(&graph, &UNICODE, 80),
+            "\
+✓ Parser  src/parser.rs:12
+  → Lexer (uses)
+✗ Lexer
+? main
+  → Core
+"
+        );
+    }
+}
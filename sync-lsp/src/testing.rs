@@ -0,0 +1,33 @@
+//! Helpers shared by the tests.
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// Temporary directory which is removed when dropped, so that failed tests
+/// don't leave it behind.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create empty directory named after the test and the process.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("islands-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}